@@ -0,0 +1,58 @@
+// Times the row scheduler against the tile orders on the built-in scene.
+//
+//     cargo bench --bench schedule
+//
+// A wide frame and a narrow one are rendered with each schedule. The narrow one has too few
+// pixels per row to keep many threads busy between the row barriers.
+
+use rayrusting::scene::default_scene;
+use rayrusting::{BvhNode, Schedule, SplitStrategy, TileOrder};
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 3;
+
+fn main() {
+    let schedules = [
+        ("rows", Schedule::Rows),
+        ("scanline", tiles(TileOrder::Scanline)),
+        ("spiral", tiles(TileOrder::Spiral)),
+        ("hilbert", tiles(TileOrder::Hilbert)),
+    ];
+
+    println!("{} threads", rayon::current_num_threads());
+    for (width, aspect) in [(160, 16.0 / 9.0), (24, 0.25)] {
+        for (name, schedule) in schedules {
+            let time = time_render(width, aspect, schedule);
+            println!(
+                "{width:>4}x{:<4} {name:<10} {:>8.1} ms",
+                (width as f32 / aspect) as i32,
+                time.as_secs_f64() * 1000.0
+            );
+        }
+    }
+}
+
+fn tiles(order: TileOrder) -> Schedule {
+    Schedule::Tiles { size: 16, order }
+}
+
+// The fastest of a few runs, to keep out noise from the rest of the machine
+fn time_render(width: i32, aspect: f32, schedule: Schedule) -> Duration {
+    let scene = default_scene(Some(1));
+    let world = BvhNode::from_list(scene.world, SplitStrategy::Sah);
+    let mut camera = scene.camera;
+    camera.image_width = width;
+    camera.aspect_ratio = aspect;
+    camera.samples_per_pixel = 8;
+    camera.max_depth = 16;
+    camera.schedule = schedule;
+
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            std::hint::black_box(camera.render_to_buffer(&world, &scene.lights));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
@@ -2,6 +2,7 @@ use crate::interval::Interval;
 use crate::ray::Ray;
 use glam::DVec3;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Default, Debug, Clone, Copy)]
 pub struct AABB {
     pub x: Interval,
@@ -43,6 +44,21 @@ impl AABB {
         }
     }
 
+    // Flat objects such as axis-aligned triangles have zero thickness along one axis, which the
+    // slab test in `hit` would always reject. Pad any such side out to a small minimum.
+    pub fn pad_to_minimums(&self) -> AABB {
+        let delta = 0.0001;
+        let pad = |i: Interval| if i.size() < delta { i.expand(delta) } else { i };
+        AABB::from_intervals(pad(self.x), pad(self.y), pad(self.z))
+    }
+
+    // Whether any side of the box is NaN, as it is for objects built from NaN coordinates.
+    pub fn has_nan(&self) -> bool {
+        [self.x, self.y, self.z]
+            .iter()
+            .any(|i| i.min.is_nan() || i.max.is_nan())
+    }
+
     pub fn axis_interval(&self, axis: i32) -> Interval {
         match axis {
             0 => self.x,
@@ -51,7 +67,42 @@ impl AABB {
         }
     }
 
+    pub fn centroid(&self) -> DVec3 {
+        DVec3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
+    // Index of the axis along which the box is widest.
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size().max(0.0);
+        let dy = self.y.size().max(0.0);
+        let dz = self.z.size().max(0.0);
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
     pub fn hit(&self, r: Ray, ray_t: Interval) -> bool {
+        self.clip(&r, ray_t).is_some()
+    }
+
+    // The part of `ray_t` over which the ray is inside the box, if any.
+    pub fn clip(&self, r: &Ray, ray_t: Interval) -> Option<Interval> {
         let mut ray_t = ray_t;
         for axis in 0..=2 {
             let ax = self.axis_interval(axis);
@@ -77,9 +128,9 @@ impl AABB {
             }
 
             if ray_t.max <= ray_t.min {
-                return false;
+                return None;
             }
         }
-        true
+        Some(ray_t)
     }
 }
@@ -0,0 +1,463 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use glam::DVec3;
+
+// Nodes with this many objects or fewer are never split any further.
+const MAX_LEAF_SIZE: usize = 2;
+
+// Number of buckets the centroid range is divided into when evaluating SAH splits.
+const SAH_BUCKETS: usize = 12;
+
+// Relative costs of visiting a node versus intersecting one of its objects, used by SAH.
+const SAH_TRAVERSAL_COST: f64 = 0.125;
+const SAH_INTERSECT_COST: f64 = 1.0;
+
+// How a node decides where to partition the objects beneath it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SplitStrategy {
+    // Split the longest axis of the centroid bounds at its midpoint.
+    Midpoint,
+    // Split the longest axis of the centroid bounds so each child holds half the objects.
+    Median,
+    // Split wherever the surface area heuristic estimates the cheapest traversal.
+    #[default]
+    Sah,
+}
+
+// A bounding volume hierarchy over a set of objects. Rays only descend into children whose
+// bounding boxes they pass through, so a hit costs O(log n) intersection tests instead of O(n).
+pub struct BvhNode<T: Hittable = Box<dyn Hittable>> {
+    bbox: AABB,
+    contents: Contents<T>,
+    // Objects without a bounding box, which can't go in the tree and are tested by every ray.
+    // Only the root has any.
+    unbounded: Vec<T>,
+}
+
+enum Contents<T: Hittable> {
+    Leaf(Vec<T>),
+    Branch(Box<BvhNode<T>>, Box<BvhNode<T>>),
+}
+
+// An object waiting to be placed in the tree, along with its cached bounds.
+struct Primitive<T> {
+    bbox: AABB,
+    centroid: DVec3,
+    object: T,
+}
+
+impl BvhNode {
+    // Builds a hierarchy that takes ownership of every object in the list.
+    pub fn from_list(list: HittableList, strategy: SplitStrategy) -> BvhNode {
+        BvhNode::new(list.into_objects(), strategy)
+    }
+}
+
+impl<T: Hittable> BvhNode<T> {
+    pub fn new(objects: Vec<T>, strategy: SplitStrategy) -> BvhNode<T> {
+        let mut primitives = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for object in objects {
+            match object.bounding_box() {
+                Some(bbox) => primitives.push(Primitive {
+                    bbox,
+                    centroid: bbox.centroid(),
+                    object,
+                }),
+                None => unbounded.push(object),
+            }
+        }
+
+        BvhNode {
+            unbounded,
+            ..Self::build(primitives, strategy)
+        }
+    }
+
+    fn build(mut primitives: Vec<Primitive<T>>, strategy: SplitStrategy) -> BvhNode<T> {
+        let bbox = primitives
+            .iter()
+            .fold(AABB::new(), |acc, p| AABB::from_boxes(&acc, &p.bbox));
+
+        if primitives.len() <= MAX_LEAF_SIZE {
+            return Self::leaf(bbox, primitives);
+        }
+
+        // Choose the split axis from the spread of the centroids rather than of the boxes
+        // themselves, since that's what actually separates the objects.
+        let centroid_bounds = primitives.iter().fold(AABB::new(), |acc, p| {
+            AABB::from_boxes(&acc, &AABB::from_points(p.centroid, p.centroid))
+        });
+        let axis = centroid_bounds.longest_axis();
+        let axis_range = centroid_bounds.axis_interval(axis as i32);
+
+        // Every centroid coincides, so no split could separate them.
+        if axis_range.size() <= 0.0 {
+            return Self::leaf(bbox, primitives);
+        }
+
+        let mid = match strategy {
+            SplitStrategy::Midpoint => {
+                let midpoint = 0.5 * (axis_range.min + axis_range.max);
+                Self::partition(&mut primitives, |p| p.centroid[axis] < midpoint)
+            }
+            SplitStrategy::Median => Self::median_split(&mut primitives, axis),
+            SplitStrategy::Sah => match Self::sah_split(&primitives, &bbox, &centroid_bounds) {
+                Some((split_axis, boundary)) => {
+                    let range = centroid_bounds.axis_interval(split_axis as i32);
+                    Self::partition(&mut primitives, |p| {
+                        Self::bucket_index(p.centroid[split_axis], range) < boundary
+                    })
+                }
+                // Splitting is estimated to be more expensive than testing every object.
+                None => return Self::leaf(bbox, primitives),
+            },
+        };
+
+        // A degenerate partition would recurse forever, so fall back to an even split.
+        let mid = if mid == 0 || mid == primitives.len() {
+            Self::median_split(&mut primitives, axis)
+        } else {
+            mid
+        };
+
+        let right = primitives.split_off(mid);
+
+        BvhNode {
+            bbox,
+            contents: Contents::Branch(
+                Box::new(Self::build(primitives, strategy)),
+                Box::new(Self::build(right, strategy)),
+            ),
+            unbounded: Vec::new(),
+        }
+    }
+
+    fn leaf(bbox: AABB, primitives: Vec<Primitive<T>>) -> BvhNode<T> {
+        BvhNode {
+            bbox,
+            contents: Contents::Leaf(primitives.into_iter().map(|p| p.object).collect()),
+            unbounded: Vec::new(),
+        }
+    }
+
+    // Reorders the primitives so those matching the predicate come first, and returns how many
+    // matched.
+    fn partition(primitives: &mut [Primitive<T>], pred: impl Fn(&Primitive<T>) -> bool) -> usize {
+        let mut mid = 0;
+        for i in 0..primitives.len() {
+            if pred(&primitives[i]) {
+                primitives.swap(i, mid);
+                mid += 1;
+            }
+        }
+        mid
+    }
+
+    fn median_split(primitives: &mut [Primitive<T>], axis: usize) -> usize {
+        let mid = primitives.len() / 2;
+        primitives
+            .select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+        mid
+    }
+
+    fn bucket_index(centroid: f64, range: Interval) -> usize {
+        let offset = (centroid - range.min) / range.size();
+        ((offset * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1)
+    }
+
+    // Evaluates the SAH cost of splitting between every pair of buckets along every axis.
+    // Returns the axis and the first bucket of the right child, or None if a leaf is cheaper.
+    fn sah_split(
+        primitives: &[Primitive<T>],
+        bbox: &AABB,
+        centroid_bounds: &AABB,
+    ) -> Option<(usize, usize)> {
+        let mut best: Option<(f64, usize, usize)> = None;
+
+        for axis in 0..3 {
+            let range = centroid_bounds.axis_interval(axis as i32);
+            if range.size() <= 0.0 {
+                continue;
+            }
+
+            let mut counts = [0usize; SAH_BUCKETS];
+            let mut bounds = [AABB::new(); SAH_BUCKETS];
+            for p in primitives {
+                let b = Self::bucket_index(p.centroid[axis], range);
+                counts[b] += 1;
+                bounds[b] = AABB::from_boxes(&bounds[b], &p.bbox);
+            }
+
+            // Sweep from the right to find the area and count of everything above each boundary.
+            let mut right_area = [0.0; SAH_BUCKETS];
+            let mut right_count = [0usize; SAH_BUCKETS];
+            let mut acc_box = AABB::new();
+            let mut acc_count = 0;
+            for b in (1..SAH_BUCKETS).rev() {
+                acc_box = AABB::from_boxes(&acc_box, &bounds[b]);
+                acc_count += counts[b];
+                right_area[b] = acc_box.surface_area();
+                right_count[b] = acc_count;
+            }
+
+            let mut acc_box = AABB::new();
+            let mut acc_count = 0;
+            for boundary in 1..SAH_BUCKETS {
+                acc_box = AABB::from_boxes(&acc_box, &bounds[boundary - 1]);
+                acc_count += counts[boundary - 1];
+                if acc_count == 0 || right_count[boundary] == 0 {
+                    continue;
+                }
+
+                let cost = acc_box.surface_area() * acc_count as f64
+                    + right_area[boundary] * right_count[boundary] as f64;
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, boundary));
+                }
+            }
+        }
+
+        let (cost, axis, boundary) = best?;
+        let split_cost = SAH_TRAVERSAL_COST + SAH_INTERSECT_COST * cost / bbox.surface_area();
+        let leaf_cost = SAH_INTERSECT_COST * primitives.len() as f64;
+
+        if split_cost < leaf_cost {
+            Some((axis, boundary))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Hittable> BvhNode<T> {
+    fn hit_tree(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        if !self.bbox.hit(*r, ray_t) {
+            return None;
+        }
+
+        match &self.contents {
+            Contents::Leaf(objects) => closest_hit(objects, r, ray_t, None),
+            Contents::Branch(left, right) => {
+                let hit_left = left.hit_tree(r, ray_t);
+                // Anything in the right child has to beat the left child's hit to matter.
+                let max = hit_left.as_ref().map_or(ray_t.max, |rec| rec.t);
+                let hit_right = right.hit_tree(r, Interval::new(ray_t.min, max));
+                hit_right.or(hit_left)
+            }
+        }
+    }
+}
+
+// The nearest hit among `objects` that beats `best`, or `best` if none does.
+fn closest_hit<'a, T: Hittable>(
+    objects: &'a [T],
+    r: &Ray,
+    ray_t: Interval,
+    best: Option<HitRecord<'a>>,
+) -> Option<HitRecord<'a>> {
+    let mut closest_so_far = best.as_ref().map_or(ray_t.max, |rec| rec.t);
+    let mut hit_record = best;
+    for object in objects {
+        if let Some(rec) = object.hit(r, Interval::new(ray_t.min, closest_so_far)) {
+            closest_so_far = rec.t;
+            hit_record = Some(rec);
+        }
+    }
+    hit_record
+}
+
+impl<T: Hittable> Hittable for BvhNode<T> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let hit = self.hit_tree(r, ray_t);
+        if self.unbounded.is_empty() {
+            return hit;
+        }
+        closest_hit(&self.unbounded, r, ray_t, hit)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.unbounded.is_empty().then_some(self.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use glam::DVec3;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_spheres(rng: &mut StdRng, count: usize) -> Vec<Sphere> {
+        (0..count)
+            .map(|_| {
+                let center = DVec3::new(
+                    rng.gen_range(-10.0..10.0),
+                    rng.gen_range(-10.0..10.0),
+                    rng.gen_range(-10.0..10.0),
+                );
+                let mat = Material::lambertian(DVec3::new(rng.gen(), rng.gen(), rng.gen()));
+                if rng.gen_bool(0.2) {
+                    let velocity = DVec3::new(0.0, rng.gen_range(0.0..1.0), 0.0);
+                    Sphere::new_moving(Ray::with_direction(center, velocity), 0.3, mat)
+                } else {
+                    Sphere::new_stationary(center, rng.gen_range(0.1..1.5), mat)
+                }
+            })
+            .collect()
+    }
+
+    fn assert_same_hits(strategy: SplitStrategy) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let spheres = random_spheres(&mut rng, 300);
+
+        let mut list = HittableList::default();
+        for sphere in spheres.iter() {
+            list.add(Box::new(sphere.clone())).unwrap();
+        }
+        let bvh = BvhNode::new(spheres, strategy);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = DVec3::new(
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(-15.0..15.0),
+            );
+            let direction = DVec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let r = Ray::with_time(origin, direction, rng.gen());
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+
+            match (list.hit(&r, ray_t), bvh.hit(&r, ray_t)) {
+                (None, None) => {}
+                (Some(expected), Some(actual)) => {
+                    hits += 1;
+                    assert!((expected.t - actual.t).abs() < 1e-9);
+                    assert!((expected.p - actual.p).length() < 1e-9);
+                    assert!((expected.normal - actual.normal).length() < 1e-9);
+                }
+                (expected, actual) => panic!(
+                    "{strategy:?}: list hit {:?} but bvh hit {:?}",
+                    expected.map(|rec| rec.t),
+                    actual.map(|rec| rec.t)
+                ),
+            }
+        }
+
+        // Make sure the comparison wasn't vacuous.
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn midpoint_matches_list_test() {
+        assert_same_hits(SplitStrategy::Midpoint);
+    }
+
+    #[test]
+    fn median_matches_list_test() {
+        assert_same_hits(SplitStrategy::Median);
+    }
+
+    #[test]
+    fn sah_matches_list_test() {
+        assert_same_hits(SplitStrategy::Sah);
+    }
+
+    #[test]
+    fn bounding_box_contains_list_bounds_test() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let spheres = random_spheres(&mut rng, 50);
+
+        let mut list = HittableList::default();
+        for sphere in spheres.iter() {
+            list.add(Box::new(sphere.clone())).unwrap();
+        }
+        let expected = list.bounding_box().unwrap();
+        let actual = BvhNode::from_list(list, SplitStrategy::Sah)
+            .bounding_box()
+            .unwrap();
+
+        for axis in 0..3 {
+            let e = expected.axis_interval(axis);
+            let a = actual.axis_interval(axis);
+            assert!((e.min - a.min).abs() < f64::EPSILON);
+            assert!((e.max - a.max).abs() < f64::EPSILON);
+        }
+    }
+
+    // The ground as an infinite plane at y = 0, which no box can hold
+    struct Ground;
+
+    impl Hittable for Ground {
+        fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+            let t = -r.origin.y / r.direction.y;
+            if !ray_t.surrounds(t) {
+                return None;
+            }
+            let mut rec = HitRecord {
+                p: r.at(t),
+                t,
+                ..Default::default()
+            };
+            rec.set_face_normal(r, DVec3::Y);
+            Some(rec)
+        }
+
+        fn bounding_box(&self) -> Option<AABB> {
+            None
+        }
+    }
+
+    #[test]
+    fn unbounded_objects_test() {
+        let sphere = || {
+            Box::new(Sphere::new_stationary(
+                DVec3::new(0.0, 1.0, 0.0),
+                0.5,
+                Material::default(),
+            ))
+        };
+        let mut list = HittableList::default();
+        list.add(sphere()).unwrap();
+        list.add(Box::new(Ground)).unwrap();
+        assert!(list.bounding_box().is_none());
+
+        let bvh = BvhNode::new(
+            vec![sphere() as Box<dyn Hittable>, Box::new(Ground)],
+            SplitStrategy::Sah,
+        );
+        assert!(bvh.bounding_box().is_none());
+
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        // Straight down onto the sphere, which is in front of the ground
+        let r = Ray::with_direction(DVec3::new(0.0, 5.0, 0.0), -DVec3::Y);
+        assert!((list.hit(&r, ray_t).unwrap().t - 3.5).abs() < 1e-9);
+        assert!((bvh.hit(&r, ray_t).unwrap().t - 3.5).abs() < 1e-9);
+        // Far from the sphere only the ground is there
+        let r = Ray::with_direction(DVec3::new(100.0, 5.0, 0.0), -DVec3::Y);
+        assert!((list.hit(&r, ray_t).unwrap().t - 5.0).abs() < 1e-9);
+        assert!((bvh.hit(&r, ray_t).unwrap().t - 5.0).abs() < 1e-9);
+        // From below the ground hides the sphere
+        let r = Ray::with_direction(DVec3::new(0.0, -1.0, 0.0), DVec3::Y);
+        assert!((bvh.hit(&r, ray_t).unwrap().t - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid_bounds_test() {
+        let mut list = HittableList::default();
+        let sphere = Sphere::new_stationary(DVec3::NAN, 1.0, Material::default());
+        assert!(matches!(
+            list.add(Box::new(sphere)),
+            Err(crate::Error::InvalidObject(_))
+        ));
+        assert!(list.is_empty());
+    }
+}
@@ -1,16 +1,89 @@
-// use crate::hittable::Hittable;
-use crate::hittable::Hittable;
-use crate::hittable::HittableList;
+use crate::error::Error;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::image::{Framebuffer, Image};
 use crate::interval::Interval;
+use crate::output::OutputFormat;
 use crate::ray::Ray;
-use crate::utils::random_in_unit_disc;
+use crate::tiles::{Schedule, Tile};
+use crate::trace::{PathEvent, PathRecorder, PixelTrace, SampleTrace};
+use crate::utils::{self, random_in_unit_disc};
 use core::f64;
 use glam::DVec3;
 use rand::Rng;
 use rayon::prelude::*;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::sync::{Arc, Mutex};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// What rays that escape the scene see.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Background {
+    // A white to blue gradient from the horizon up
+    #[default]
+    Sky,
+    // A single color in every direction. Black leaves the scene lit only by its lights.
+    Color(DVec3),
+}
+
+impl Background {
+    pub fn color(&self, r: &Ray) -> DVec3 {
+        match self {
+            Background::Sky => {
+                let blue = DVec3::new(0.5, 0.7, 1.0);
+                let white = DVec3::new(1.0, 1.0, 1.0);
+                let unit_direction = r.direction.normalize();
+                let a = 0.5 * (unit_direction.y + 1.0);
+                white.lerp(blue, a)
+            }
+            Background::Color(color) => *color,
+        }
+    }
+}
+
+// How light sources are found from diffuse surfaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LightSampling {
+    // Only by scattered rays happening to hit them
+    Bsdf,
+    // Only by shadow rays aimed at the lights
+    Lights,
+    // Both, weighted against each other with the power heuristic
+    #[default]
+    Mis,
+}
+
+// What can cut a progressive render short, and how often it shows how far it has got.
+#[derive(Debug, Clone, Default)]
+pub struct Progressive {
+    // Wall-clock time after which no more tiles are started
+    pub time_limit: Option<Duration>,
+    // Set from anywhere, such as a Ctrl-C handler, to stop at the next tile
+    pub stop: Arc<AtomicBool>,
+    // The least time between checkpoints. None never checkpoints.
+    pub checkpoint_interval: Option<Duration>,
+}
+
+// Settings for taking fewer samples in pixels that have already converged. No pixel takes more
+// than the camera's samples_per_pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adaptive {
+    // Samples every pixel takes before its noise is judged
+    pub min_samples: i32,
+    // The noise, as a fraction of full brightness on screen, below which a pixel is left alone.
+    // 0.01 is hard to see in most scenes.
+    pub threshold: f64,
+}
+
+impl Default for Adaptive {
+    fn default() -> Adaptive {
+        Adaptive {
+            min_samples: 16,
+            threshold: 0.01,
+        }
+    }
+}
 
 pub struct Camera {
     pub aspect_ratio: f32,
@@ -21,7 +94,6 @@ pub struct Camera {
     pixel_delta_u: DVec3,
     pixel_delta_v: DVec3,
     pub samples_per_pixel: i32,
-    pixel_samples_scale: f64,
     pub max_depth: i32,
     pub vfov: f64,
     pub look_from: DVec3,
@@ -32,6 +104,26 @@ pub struct Camera {
     pub focus_dist: f64,
     defocus_disc_u: DVec3,
     defocus_disc_v: DVec3,
+
+    pub background: Background,
+    pub light_sampling: LightSampling,
+    // Makes renders repeatable: the same seed and settings give the same image, whatever the
+    // thread count. Without one every render draws a fresh seed.
+    pub seed: Option<u64>,
+    // How the work is split between threads
+    pub schedule: Schedule,
+    // Stops sampling converged pixels early when set
+    pub adaptive: Option<Adaptive>,
+
+    pub output: PathBuf,
+    // Overrides the format picked from the output file's extension
+    pub output_format: Option<OutputFormat>,
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera::new()
+    }
 }
 
 impl Camera {
@@ -45,7 +137,6 @@ impl Camera {
             pixel_delta_u: DVec3::new(0.0, 0.0, 0.0),
             pixel_delta_v: DVec3::new(0.0, 0.0, 0.0),
             samples_per_pixel: 10,
-            pixel_samples_scale: 0.0,
             max_depth: 10,
             vfov: 90.0,
             look_from: DVec3::new(0.0, 0.0, 0.0),
@@ -55,20 +146,46 @@ impl Camera {
             focus_dist: 10.0,
             defocus_disc_u: DVec3::new(0.0, 1.0, 0.0),
             defocus_disc_v: DVec3::new(0.0, 1.0, 0.0),
+            background: Background::Sky,
+            light_sampling: LightSampling::Mis,
+            seed: None,
+            schedule: Schedule::default(),
+            adaptive: None,
+            output: PathBuf::from("image.ppm"),
+            output_format: None,
         }
     }
 
-    fn initialize(&mut self) {
-        // Calculate the image height, and ensure that it's at least 1.
-        self.image_height = (self.image_width as f32 / self.aspect_ratio) as i32;
-        self.image_height = if self.image_height < 1 {
-            1
-        } else {
-            self.image_height
-        };
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
+    // The height of the rendered image, from its width and aspect ratio.
+    pub fn image_height(&self) -> i32 {
+        // Ensure that it's at least 1.
+        let image_height = (self.image_width as f32 / self.aspect_ratio) as i32;
+        image_height.max(1)
+    }
+
+    // The format the image will be written in, if one was set or the output extension is known.
+    pub fn output_format(&self) -> Option<OutputFormat> {
+        self.output_format
+            .or_else(|| OutputFormat::from_path(&self.output))
+    }
 
-        // Scaling factor to divide a pixel's color by when summating all contributing samples
-        self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
+    // Where rays start from, ignoring depth of field. Only up to date after `prepare` or a render.
+    pub fn center(&self) -> DVec3 {
+        self.center
+    }
+
+    // Recomputes the viewport from the public settings. Rendering does this itself, so it's only
+    // needed to inspect the derived values or generate rays by hand.
+    pub fn prepare(&mut self) {
+        self.initialize();
+    }
+
+    fn initialize(&mut self) {
+        self.image_height = self.image_height();
 
         self.center = self.look_from;
 
@@ -104,98 +221,370 @@ impl Camera {
         self.defocus_disc_v = v * defocus_radius;
     }
 
-    pub fn render(&mut self, world: &HittableList) {
-        // Debug flag for verbosity
-        let debug = true;
+    // Renders the world and writes it to the output file.
+    pub fn render(&mut self, world: &dyn Hittable, lights: &HittableList) -> Result<(), Error> {
+        let format = self
+            .output_format()
+            .ok_or_else(|| Error::UnknownFormat(self.output.clone()))?;
 
+        let image = self.render_to_buffer(world, lights);
+        image
+            .write(&self.output, format)
+            .map_err(|source| Error::Write {
+                path: self.output.clone(),
+                source,
+            })
+    }
+
+    // Renders the world into memory, leaving the output settings unused.
+    //
+    // `lights` holds the emissive objects to aim shadow rays at, and may be empty. Emitters left
+    // out of it can still be hit by scattered rays, but not by shadow rays. With `Lights`
+    // sampling that means they only show up when seen directly or through mirrors and glass.
+    pub fn render_to_buffer(&mut self, world: &dyn Hittable, lights: &HittableList) -> Image {
         self.initialize();
+        let seed = self.seed.unwrap_or_else(rand::random);
 
-        // File I/O Setup
-        let file = File::create("image.ppm")
-            .map_err(|e| format!("Failed to create file: {}", e))
-            .unwrap_or_else(|e| panic!("{}", e));
-        let mut writer = BufWriter::new(file);
+        match self.schedule {
+            Schedule::Rows => self.render_rows(seed, world, lights),
+            Schedule::Tiles { .. } => {
+                let (width, height) = (self.image_width as usize, self.image_height as usize);
+                let tiles = self.schedule.tiles(width, height);
+                log::debug!("Rendering {} tiles ({:?})", tiles.len(), self.schedule);
 
-        Self::write_header(&mut writer, &self.image_width, &self.image_height);
+                let frame = Framebuffer::new(width, height);
+                let samples = 0..self.samples_per_pixel;
+                self.render_pass(seed, samples, &tiles, &frame, world, lights, &|| false);
+                frame.to_image()
+            }
+        }
+    }
 
-        for row in 0..self.image_height {
-            if debug {
-                let rem = self.image_height - 1;
-                println!("Writing scanline {row} of {rem}");
+    // Renders the world a pass of one sample per pixel at a time, until every pixel has
+    // `samples_per_pixel` samples or `limits` cuts it short. Every so often `on_checkpoint` is
+    // given the image so far.
+    //
+    // Pass n takes the same sample n that `render_to_buffer` would, so a render left to finish
+    // matches it exactly. One stopped partway through a pass has an extra sample in some pixels,
+    // which the image's sample counts show.
+    pub fn render_progressive(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        limits: &Progressive,
+        mut on_checkpoint: impl FnMut(&Image),
+    ) -> Image {
+        self.initialize();
+        let seed = self.seed.unwrap_or_else(rand::random);
+
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        let tiles = self.schedule.tiles(width, height);
+        let frame = Framebuffer::new(width, height);
+
+        let start = Instant::now();
+        let mut last_checkpoint = start;
+        let stop = || {
+            limits.stop.load(Ordering::Relaxed)
+                || limits
+                    .time_limit
+                    .is_some_and(|limit| start.elapsed() >= limit)
+        };
+
+        for pass in 0..self.samples_per_pixel {
+            let finished =
+                self.render_pass(seed, pass..pass + 1, &tiles, &frame, world, lights, &stop);
+            if !finished {
+                log::info!(
+                    "Stopped during pass {} after {:.1?}",
+                    pass + 1,
+                    start.elapsed()
+                );
+                break;
             }
+            let total = self.samples_per_pixel;
+            log::debug!(
+                "Finished pass {} of {total} after {:.1?}",
+                pass + 1,
+                start.elapsed()
+            );
 
-            // Create a buffer to store pixels and coordinates in for the asynchronous rendering
-            let row_buffer: Arc<Mutex<Vec<(usize, DVec3)>>> = Arc::new(Mutex::new(Vec::new()));
+            let last_pass = pass + 1 == self.samples_per_pixel;
+            let due = limits
+                .checkpoint_interval
+                .is_some_and(|interval| last_checkpoint.elapsed() >= interval);
+            if due && !last_pass {
+                on_checkpoint(&frame.to_image());
+                last_checkpoint = Instant::now();
+            }
+        }
 
-            (0..self.image_width).into_par_iter().for_each(|col| {
-                let mut pixel_color = DVec3::new(0.0, 0.0, 0.0);
-
-                for _ in 0..self.samples_per_pixel {
-                    let r = Self::get_ray(self, col, row);
-                    let debug2 = col == 20000 && row == 150;
-                    if debug2 {
-                        println!("camera::self.look_from: {:?}", self.look_from);
-                    }
-                    pixel_color += Self::ray_color(r, self.max_depth, world, debug2);
-                }
+        frame.to_image()
+    }
+
+    // Renders the frame a row at a time, spreading each row's pixels over the threads.
+    fn render_rows(&self, seed: u64, world: &dyn Hittable, lights: &HittableList) -> Image {
+        let frame = Framebuffer::new(self.image_width as usize, self.image_height as usize);
 
-                let pixel_color = self.pixel_samples_scale * pixel_color;
-                let mut buffer = row_buffer.lock().unwrap();
-                buffer.push((col.try_into().unwrap(), pixel_color));
+        for row in 0..self.image_height {
+            log::debug!("Writing scanline {row} of {}", self.image_height - 1);
+
+            (0..self.image_width).into_par_iter().for_each(|col| {
+                let samples = 0..self.samples_per_pixel;
+                self.sample_pixel(seed, col, row, samples, &frame, world, lights);
             });
+        }
 
-            // Once the row is fully drawn by each thread, get the row_buffer and allow mutation
-            let mut row_buffer = Arc::try_unwrap(row_buffer).unwrap().into_inner().unwrap();
+        frame.to_image()
+    }
 
-            // sort the row_buffer by column position
-            row_buffer.sort_by_key(|&(col, _)| col);
+    // Adds the given samples of every pixel in the tiles to the frame. Each thread takes the
+    // next tile as soon as it's free, so nobody waits for anyone else until the last tile is
+    // done. Once `stop` returns true no more tiles are started, and this returns false.
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(
+        &self,
+        seed: u64,
+        samples: Range<i32>,
+        tiles: &[Tile],
+        frame: &Framebuffer,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        stop: &(dyn Fn() -> bool + Sync),
+    ) -> bool {
+        let next_tile = AtomicUsize::new(0);
+        let stopped = AtomicBool::new(false);
 
-            // Print it
-            for (_, pixel_color) in row_buffer {
-                if let Err(e) = Self::write_color(&mut writer, &pixel_color) {
-                    eprintln!("Error writing color: {}", e);
+        rayon::broadcast(|_| {
+            while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                if stop() {
+                    stopped.store(true, Ordering::Relaxed);
+                    break;
+                }
+                for (x, y) in tile.pixels() {
+                    let (x, y) = (x as i32, y as i32);
+                    self.sample_pixel(seed, x, y, samples.clone(), frame, world, lights);
                 }
             }
+        });
+
+        !stopped.load(Ordering::Relaxed)
+    }
+
+    // Adds the given samples through pixel (x, y) to the frame, stopping early once the pixel
+    // has converged if sampling is adaptive.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_pixel(
+        &self,
+        seed: u64,
+        x: i32,
+        y: i32,
+        samples: Range<i32>,
+        frame: &Framebuffer,
+        world: &dyn Hittable,
+        lights: &HittableList,
+    ) {
+        let (fx, fy) = (x as usize, y as usize);
+
+        for sample in samples {
+            if let Some(adaptive) = &self.adaptive {
+                let taken = frame.sample_count(fx, fy) as i32;
+                if taken >= adaptive.min_samples && frame.error(fx, fy) < adaptive.threshold {
+                    break;
+                }
+            }
+
+            // Every sample gets its own random stream, so it doesn't matter which thread draws it
+            // or what that thread drew before.
+            let pixel = y as u64 * self.image_width as u64 + x as u64;
+            utils::reseed(seed, pixel, sample as u64);
+
+            let r = Self::get_ray(self, x, y);
+            let color = self.ray_color(r, self.max_depth, world, lights, 1.0, &mut ());
+            frame.add_sample(fx, fy, color);
+        }
+    }
+
+    // Follows every sample a render with the same seed would take through pixel (x, y), noting
+    // each step. Adaptive sampling is ignored, so all samples_per_pixel samples are traced.
+    pub fn trace_pixel(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        x: i32,
+        y: i32,
+    ) -> PixelTrace {
+        self.initialize();
+        let seed = self.seed.unwrap_or_else(rand::random);
+
+        let samples = (0..self.samples_per_pixel)
+            .map(|sample| {
+                let pixel = y as u64 * self.image_width as u64 + x as u64;
+                utils::reseed(seed, pixel, sample as u64);
+
+                let r = Self::get_ray(self, x, y);
+                let mut events = vec![PathEvent::CameraRay {
+                    origin: r.origin,
+                    direction: r.direction,
+                    time: r.time,
+                }];
+                let color = self.ray_color(r, self.max_depth, world, lights, 1.0, &mut events);
+                SampleTrace {
+                    sample,
+                    color,
+                    events,
+                }
+            })
+            .collect();
+
+        PixelTrace {
+            x,
+            y,
+            seed,
+            samples,
         }
     }
 
-    fn ray_color(r: Ray, depth: i32, world: &HittableList, debug: bool) -> DVec3 {
+    // Radiance arriving back along r. Light emitted by the first surface hit is scaled by
+    // emission_weight, which is the MIS weight of the scattering that produced r.
+    fn ray_color(
+        &self,
+        r: Ray,
+        depth: i32,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        emission_weight: f64,
+        recorder: &mut impl PathRecorder,
+    ) -> DVec3 {
+        let bounce = self.max_depth - depth;
         if depth <= 0 {
+            recorder.record(PathEvent::DepthLimit { bounce });
             return DVec3::new(0.0, 0.0, 0.0);
         }
 
-        if debug {
-            println!("Checking for hit at depth {}:", depth);
-        }
+        let Some(rec) = world.hit(&r, Interval::new(f64::EPSILON, f64::INFINITY)) else {
+            // If nothing was hit before the depth base case, return the background color
+            let background = self.background.color(&r);
+            recorder.record(PathEvent::Miss { bounce, background });
+            return background;
+        };
 
-        if let Some(rec) = world.hit(&r, Interval::new(f64::EPSILON, f64::INFINITY), debug) {
-            if debug {
-                println!("Did hit. Getting attenuation, scatter:");
-            }
-            let (attenuation, scattered, keeps_bouncing) = rec.mat.scatter(r, &rec, debug).unwrap();
-            if keeps_bouncing {
-                if debug {
-                    println!("camera::ray_color::rec: {:?}", rec);
-                    println!("camera::ray_color::scattered: {:?}", scattered);
-                    println!("!!!");
+        let emitted = emission_weight * rec.mat.emitted(&r, &rec);
+        recorder.record(PathEvent::Hit {
+            bounce,
+            t: rec.t,
+            point: rec.p,
+            normal: rec.normal,
+            front_face: rec.front_face,
+            material: rec.mat.name(),
+            emitted,
+        });
+
+        let wo = -r.direction.normalize();
+        let Some(sample) = rec.mat.sample(wo, &rec) else {
+            // Absorbed, or a light that doesn't scatter
+            recorder.record(PathEvent::Absorbed { bounce });
+            return emitted;
+        };
+        let attenuation = sample.weight;
+        let scattered = Ray::with_time(rec.p, sample.direction, r.time);
+
+        // Light reached by a shadow ray has taken one more bounce, so it's only counted where a
+        // scattered ray could still pick up emission.
+        let sample_lights = self.light_sampling != LightSampling::Bsdf
+            && !lights.is_empty()
+            && !rec.mat.is_specular()
+            && depth > 1;
+
+        let (direct, next_weight) = if sample_lights {
+            let direct = self.sample_lights(&r, &rec, world, lights, bounce, recorder);
+            let next_weight = match self.light_sampling {
+                // Shadow rays can't find lights through a delta lobe, so it's all down to this ray.
+                _ if sample.delta => 1.0,
+                LightSampling::Mis => {
+                    power_heuristic(sample.pdf, lights.pdf_value(rec.p, scattered.direction))
                 }
-                return attenuation
-                    * Self::ray_color(scattered, depth - 1, world, debug && (depth - 1) >= 9);
-            } else {
-                return DVec3::new(0.0, 0.0, 0.0);
-            }
+                // The shadow ray already accounted for the lights.
+                _ => 0.0,
+            };
+            (direct, next_weight)
+        } else {
+            (DVec3::ZERO, 1.0)
+        };
+
+        recorder.record(PathEvent::Scatter {
+            bounce,
+            direction: scattered.direction,
+            attenuation,
+        });
+
+        emitted
+            + direct
+            + attenuation
+                * self.ray_color(scattered, depth - 1, world, lights, next_weight, recorder)
+    }
+
+    // Estimates the light reaching rec directly from one of the lights, by aiming a shadow ray at
+    // a random point on it.
+    fn sample_lights(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        bounce: i32,
+        recorder: &mut impl PathRecorder,
+    ) -> DVec3 {
+        let direction = lights.random(rec.p);
+        let pdf = lights.pdf_value(rec.p, direction);
+        let contribution = self.light_contribution(r, rec, world, direction, pdf);
+        recorder.record(PathEvent::LightSample {
+            bounce,
+            direction,
+            pdf,
+            contribution,
+        });
+        contribution
+    }
+
+    // The light arriving from `direction`, which lights are sampled in with density pdf
+    fn light_contribution(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        direction: DVec3,
+        light_pdf: f64,
+    ) -> DVec3 {
+        if light_pdf <= 0.0 {
+            return DVec3::ZERO;
+        }
+
+        let wo = -r.direction.normalize();
+        let wi = direction.normalize();
+        let f = rec.mat.eval(wo, wi, rec);
+        if f == DVec3::ZERO {
+            return DVec3::ZERO;
         }
 
-        // If nothing was hit before the depth base case, return the sky box color
-        let blue = DVec3::new(0.5, 0.7, 1.0);
-        let white = DVec3::new(1.0, 1.0, 1.0);
-        let unit_direction = r.direction.normalize();
-        let a = 0.5 * (unit_direction.y + 1.0);
-        white.lerp(blue, a)
+        // Whatever the shadow ray hits first is either the light or something blocking it, so
+        // its emission is what arrives.
+        let shadow_ray = Ray::with_time(rec.p, direction, r.time);
+        let Some(light_rec) = world.hit(&shadow_ray, Interval::new(f64::EPSILON, f64::INFINITY))
+        else {
+            return DVec3::ZERO;
+        };
+        let radiance = light_rec.mat.emitted(&shadow_ray, &light_rec);
+
+        let weight = match self.light_sampling {
+            LightSampling::Mis => power_heuristic(light_pdf, rec.mat.pdf(wo, wi, rec)),
+            _ => 1.0,
+        };
+        weight * f * radiance / light_pdf
     }
 
-    fn get_ray(&self, x: i32, y: i32) -> Ray {
-        let mut rng = rand::thread_rng();
+    // A ray through a random point in pixel (x, y), counting from the top left.
+    pub fn get_ray(&self, x: i32, y: i32) -> Ray {
+        let mut rng = utils::rng();
 
         let offset = if self.samples_per_pixel > 1 {
             Self::sample_square()
@@ -226,39 +615,445 @@ impl Camera {
     }
 
     fn sample_square() -> DVec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = utils::rng();
 
         // Returns the vector to a random point in the [-.5,-.5]-[+.5,+.5] unit square.
         DVec3::new(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5, 0.0)
     }
+}
+
+// The weight for a sample drawn with density pdf, when the other strategy would have drawn it
+// with density other_pdf (Veach's power heuristic with an exponent of 2).
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+// Sets up a camera in one expression, starting from the same defaults as `Camera::new`.
+#[derive(Default)]
+pub struct CameraBuilder {
+    camera: Camera,
+}
+
+impl CameraBuilder {
+    pub fn aspect_ratio(mut self, aspect_ratio: f32) -> CameraBuilder {
+        self.camera.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    pub fn image_width(mut self, image_width: i32) -> CameraBuilder {
+        self.camera.image_width = image_width;
+        self
+    }
+
+    pub fn samples_per_pixel(mut self, samples_per_pixel: i32) -> CameraBuilder {
+        self.camera.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: i32) -> CameraBuilder {
+        self.camera.max_depth = max_depth;
+        self
+    }
+
+    pub fn vfov(mut self, vfov: f64) -> CameraBuilder {
+        self.camera.vfov = vfov;
+        self
+    }
+
+    pub fn look_from(mut self, look_from: DVec3) -> CameraBuilder {
+        self.camera.look_from = look_from;
+        self
+    }
 
-    fn write_header(writer: &mut BufWriter<File>, width: &i32, height: &i32) {
-        writeln!(writer, "P3").unwrap();
-        writeln!(writer, "{width} {height}").unwrap();
-        writeln!(writer, "255").unwrap();
+    pub fn look_at(mut self, look_at: DVec3) -> CameraBuilder {
+        self.camera.look_at = look_at;
+        self
     }
 
-    fn write_color(writer: &mut BufWriter<File>, color: &DVec3) -> Result<(), std::io::Error> {
-        let r = color.x;
-        let g = color.y;
-        let b = color.z;
+    pub fn look_up(mut self, look_up: DVec3) -> CameraBuilder {
+        self.camera.look_up = look_up;
+        self
+    }
+
+    pub fn defocus_angle(mut self, defocus_angle: f64) -> CameraBuilder {
+        self.camera.defocus_angle = defocus_angle;
+        self
+    }
 
-        let r = Self::linear_to_gamma(r);
-        let g = Self::linear_to_gamma(g);
-        let b = Self::linear_to_gamma(b);
+    pub fn focus_dist(mut self, focus_dist: f64) -> CameraBuilder {
+        self.camera.focus_dist = focus_dist;
+        self
+    }
+
+    pub fn light_sampling(mut self, light_sampling: LightSampling) -> CameraBuilder {
+        self.camera.light_sampling = light_sampling;
+        self
+    }
+
+    pub fn background(mut self, background: Background) -> CameraBuilder {
+        self.camera.background = background;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> CameraBuilder {
+        self.camera.seed = Some(seed);
+        self
+    }
+
+    pub fn schedule(mut self, schedule: Schedule) -> CameraBuilder {
+        self.camera.schedule = schedule;
+        self
+    }
+
+    pub fn adaptive(mut self, adaptive: Adaptive) -> CameraBuilder {
+        self.camera.adaptive = Some(adaptive);
+        self
+    }
+
+    pub fn output(mut self, output: impl Into<PathBuf>) -> CameraBuilder {
+        self.camera.output = output.into();
+        self
+    }
+
+    pub fn output_format(mut self, output_format: OutputFormat) -> CameraBuilder {
+        self.camera.output_format = Some(output_format);
+        self
+    }
+
+    // Finishes the camera with its viewport already computed.
+    pub fn build(self) -> Camera {
+        let mut camera = self.camera;
+        camera.initialize();
+        camera
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HittableList;
+    use crate::tiles::TileOrder;
+
+    #[test]
+    fn render_to_buffer_test() {
+        let mut cam = Camera::new();
+        cam.image_width = 3;
+        cam.samples_per_pixel = 1;
+
+        // With nothing in the way, every ray sees the sky.
+        let image = cam.render_to_buffer(&HittableList::default(), &HittableList::default());
+
+        assert_eq!((image.width, image.height), (3, 3));
+        assert_eq!(image.pixels.len(), 9);
+        // The center pixel looks straight ahead, halfway between white and blue.
+        assert!(image
+            .get(1, 1)
+            .abs_diff_eq(DVec3::new(0.75, 0.85, 1.0), 1e-12));
+        // The sky gets bluer towards the top.
+        assert!(image.get(1, 0).x < image.get(1, 1).x);
+        assert!(image.get(1, 2).x > image.get(1, 1).x);
+    }
 
-        let intensity = Interval::new(0.000, 0.999);
-        let r: i32 = (intensity.clamp(r) * 256.) as i32;
-        let g: i32 = (intensity.clamp(g) * 256.) as i32;
-        let b: i32 = (intensity.clamp(b) * 256.) as i32;
+    #[test]
+    fn builder_test() {
+        let cam = Camera::builder()
+            .image_width(160)
+            .aspect_ratio(2.0)
+            .look_from(DVec3::new(1.0, 2.0, 3.0))
+            .build();
 
-        writeln!(writer, "{r:>3} {g:>3} {b:>3}")
+        assert_eq!(cam.image_width, 160);
+        assert_eq!(cam.image_height(), 80);
+        assert_eq!(cam.center(), DVec3::new(1.0, 2.0, 3.0));
+        assert_eq!(cam.samples_per_pixel, Camera::new().samples_per_pixel);
     }
 
-    fn linear_to_gamma(linear_component: f64) -> f64 {
-        if linear_component > 0.0 {
-            return linear_component.sqrt();
+    #[test]
+    fn schedules_agree_test() {
+        use crate::material::Material;
+        use crate::sphere::Sphere;
+
+        let mut world = HittableList::default();
+        world
+            .add(Box::new(Sphere::new_stationary(
+                DVec3::new(0.0, 0.0, -1.0),
+                0.5,
+                Material::lambertian(DVec3::splat(0.5)),
+            )))
+            .unwrap();
+
+        // Pixels are seeded by position, so how they're shared out doesn't change the image.
+        let render = |schedule| {
+            Camera::builder()
+                .image_width(13)
+                .aspect_ratio(1.3)
+                .samples_per_pixel(2)
+                .seed(5)
+                .schedule(schedule)
+                .build()
+                .render_to_buffer(&world, &HittableList::default())
+        };
+        let rows = render(Schedule::Rows);
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = render(Schedule::Tiles { size: 4, order });
+            assert_eq!(rows, tiles, "{order:?}");
+        }
+    }
+
+    #[test]
+    fn progressive_test() {
+        use crate::material::Material;
+        use crate::sphere::Sphere;
+
+        let mut world = HittableList::default();
+        world
+            .add(Box::new(Sphere::new_stationary(
+                DVec3::new(0.0, 0.0, -1.0),
+                0.5,
+                Material::lambertian(DVec3::splat(0.5)),
+            )))
+            .unwrap();
+        let camera = |samples_per_pixel| {
+            Camera::builder()
+                .image_width(6)
+                .samples_per_pixel(samples_per_pixel)
+                .seed(9)
+                .build()
+        };
+        let lights = HittableList::default();
+
+        // Left to finish, the passes add up to the same image as rendering in one go.
+        let limits = Progressive {
+            checkpoint_interval: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let mut checkpoints = 0;
+        let image = camera(3).render_progressive(&world, &lights, &limits, |_| checkpoints += 1);
+        assert_eq!(image, camera(3).render_to_buffer(&world, &lights));
+        // After every pass but the last, which is the result itself
+        assert_eq!(checkpoints, 2);
+
+        // Stopped between passes, the result is what the last checkpoint showed.
+        let mut first_pass = None;
+        let image = camera(3).render_progressive(&world, &lights, &limits, |image| {
+            first_pass = Some(image.clone());
+            limits.stop.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(Some(image), first_pass);
+
+        // Out of time before starting, nothing gets sampled.
+        let limits = Progressive {
+            time_limit: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let image = camera(3).render_progressive(&world, &lights, &limits, |_| {});
+        assert!(image.pixels.iter().all(|&c| c == DVec3::ZERO));
+    }
+
+    #[test]
+    fn adaptive_sampling_test() {
+        use crate::material::Material;
+        use crate::sphere::Sphere;
+
+        // A rough sphere, noisy where it's lit by the sky, in front of the smooth sky itself
+        let mut world = HittableList::default();
+        world
+            .add(Box::new(Sphere::new_stationary(
+                DVec3::new(0.0, 0.0, -1.0),
+                0.4,
+                Material::lambertian(DVec3::splat(0.5)),
+            )))
+            .unwrap();
+        let adaptive = Adaptive {
+            min_samples: 4,
+            threshold: 0.01,
+        };
+        let mut camera = Camera::builder()
+            .image_width(8)
+            .samples_per_pixel(64)
+            .seed(3)
+            .adaptive(adaptive)
+            .build();
+
+        let image = camera.render_to_buffer(&world, &HittableList::default());
+        let count = |x, y| image.sample_count(x, y).unwrap();
+        for (x, y) in [(0, 0), (7, 0), (0, 7), (7, 7)] {
+            assert!(
+                (4..64).contains(&count(x, y)),
+                "({x}, {y}): {}",
+                count(x, y)
+            );
+        }
+        assert!(count(4, 4) > count(0, 0), "{}", count(4, 4));
+
+        // Progressive passes skip converged pixels the same way.
+        let progressive = camera.render_progressive(
+            &world,
+            &HittableList::default(),
+            &Progressive::default(),
+            |_| {},
+        );
+        assert_eq!(progressive.sample_count(0, 0), Some(count(0, 0)));
+    }
+
+    #[test]
+    fn trace_pixel_test() {
+        use crate::material::Material;
+        use crate::sphere::Sphere;
+
+        let mut world = HittableList::default();
+        world
+            .add(Box::new(Sphere::new_stationary(
+                DVec3::new(0.0, 0.0, -1.0),
+                0.5,
+                Material::lambertian(DVec3::splat(0.5)),
+            )))
+            .unwrap();
+        let camera = || {
+            Camera::builder()
+                .image_width(5)
+                .samples_per_pixel(3)
+                .seed(11)
+                .build()
+        };
+        let lights = HittableList::default();
+
+        // The trace follows the same samples as the render.
+        let trace = camera().trace_pixel(&world, &lights, 2, 2);
+        let image = camera().render_to_buffer(&world, &lights);
+        assert_eq!(trace.samples.len(), 3);
+        let sum: DVec3 = trace.samples.iter().map(|s| s.color).sum();
+        assert!((sum / 3.0).abs_diff_eq(image.get(2, 2), 1e-12));
+
+        // Each path starts at the camera, hits the sphere and ends in the sky or the depth limit.
+        for sample in &trace.samples {
+            assert!(matches!(sample.events[0], PathEvent::CameraRay { .. }));
+            assert!(matches!(
+                sample.events[1],
+                PathEvent::Hit {
+                    bounce: 0,
+                    material: "principled",
+                    ..
+                }
+            ));
+            assert!(matches!(
+                sample.events.last(),
+                Some(PathEvent::Miss { .. } | PathEvent::DepthLimit { .. })
+            ));
         }
-        0.0
+
+        let json: serde_json::Value = serde_json::from_str(&trace.to_json()).unwrap();
+        assert_eq!(json["samples"][0]["events"][1]["event"], "hit");
+        assert_eq!(json["samples"][0]["events"][1]["material"], "principled");
+    }
+
+    #[test]
+    fn background_color_test() {
+        let mut cam = Camera::builder()
+            .image_width(2)
+            .samples_per_pixel(1)
+            .background(Background::Color(DVec3::new(0.1, 0.2, 0.3)))
+            .build();
+
+        let image = cam.render_to_buffer(&HittableList::default(), &HittableList::default());
+        assert!(image.pixels.iter().all(|&c| c == DVec3::new(0.1, 0.2, 0.3)));
+    }
+
+    #[test]
+    fn light_seen_directly_test() {
+        use crate::material::Material;
+        use crate::sphere::Sphere;
+
+        let mut world = HittableList::default();
+        world
+            .add(Box::new(Sphere::new_stationary(
+                DVec3::new(0.0, 0.0, -2.0),
+                1.0,
+                Material::DiffuseLight {
+                    emit: DVec3::new(4.0, 2.0, 1.0).into(),
+                    two_sided: false,
+                },
+            )))
+            .unwrap();
+        let mut cam = Camera::builder()
+            .image_width(3)
+            .samples_per_pixel(1)
+            .background(Background::Color(DVec3::ZERO))
+            .build();
+
+        // Lights don't scatter, so the middle pixel is exactly the emitted radiance, unclamped.
+        let image = cam.render_to_buffer(&world, &HittableList::default());
+        assert_eq!(image.get(1, 1), DVec3::new(4.0, 2.0, 1.0));
+    }
+
+    // A diffuse plane lit by a small sphere light straight above, with nothing else around. The
+    // radiance leaving the plane there is albedo * emit * sin^2 of the light's angular radius.
+    #[test]
+    fn light_sampling_strategies_agree_test() {
+        use crate::material::Material;
+        use crate::sphere::Sphere;
+
+        let albedo = 0.5;
+        let emit = 10.0;
+        let light = Sphere::new_stationary(
+            DVec3::new(0.0, 2.0, 0.0),
+            0.5,
+            Material::DiffuseLight {
+                emit: DVec3::splat(emit).into(),
+                two_sided: false,
+            },
+        );
+
+        let mut world = HittableList::default();
+        world
+            .add(Box::new(Sphere::new_stationary(
+                DVec3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                Material::lambertian(DVec3::splat(albedo)),
+            )))
+            .unwrap();
+        world.add(Box::new(light.clone())).unwrap();
+        let mut lights = HittableList::default();
+        lights.add(Box::new(light)).unwrap();
+
+        let expected = albedo * emit * (0.5f64 / 2.0).powi(2);
+
+        // A ray from the side landing right under the light
+        let r = Ray::with_direction(DVec3::new(1.0, 1.0, 0.0), DVec3::new(-1.0, -1.0, 0.0));
+        let samples = 100_000;
+        for light_sampling in [
+            LightSampling::Bsdf,
+            LightSampling::Lights,
+            LightSampling::Mis,
+        ] {
+            let cam = Camera::builder()
+                .light_sampling(light_sampling)
+                .background(Background::Color(DVec3::ZERO))
+                .build();
+
+            // Each strategy draws from the same fixed stream, so none of them can miss by chance
+            utils::reseed(1, 0, 0);
+            let mean = (0..samples)
+                .map(|_| cam.ray_color(r, 3, &world, &lights, 1.0, &mut ()).x)
+                .sum::<f64>()
+                / samples as f64;
+
+            assert!(
+                (mean - expected).abs() < 0.02,
+                "{light_sampling:?}: {mean} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn power_heuristic_test() {
+        assert_eq!(power_heuristic(1.0, 1.0), 0.5);
+        assert_eq!(power_heuristic(2.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert!((power_heuristic(3.0, 1.0) + power_heuristic(1.0, 3.0) - 1.0).abs() < 1e-12);
     }
 }
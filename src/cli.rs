@@ -0,0 +1,653 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use glam::DVec3;
+use rayrusting::bvh::SplitStrategy;
+use rayrusting::camera::{Adaptive, Background, Camera, LightSampling};
+use rayrusting::output::{ExrCompression, ExrPrecision, OutputFormat};
+use rayrusting::tiles::{Schedule, TileOrder, DEFAULT_TILE_SIZE};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(version, about = "Renders scenes with a path tracer")]
+pub struct Cli {
+    // Renders with default settings when no subcommand is given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Log more detail; repeat for even more. RUST_LOG overrides this
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only log warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+impl Cli {
+    pub fn log_level(&self) -> log::LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => log::LevelFilter::Warn,
+            (false, 0) => log::LevelFilter::Info,
+            (false, 1) => log::LevelFilter::Debug,
+            (false, _) => log::LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render a scene to an image file
+    Render(RenderArgs),
+    /// Print statistics about a scene without rendering it
+    Info(InfoArgs),
+}
+
+#[derive(Args, Default)]
+pub struct SceneArgs {
+    /// Scene description file to load [default: the built-in scene]
+    #[arg(long)]
+    pub scene: Option<PathBuf>,
+
+    /// Seed for the built-in scene's object placement and for rendering, which makes output
+    /// reproducible. Overrides a scene file's camera seed
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+#[derive(Args, Default)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub camera: CameraArgs,
+
+    /// Number of worker threads [default: one per CPU]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub threads: Option<u16>,
+
+    /// Acceleration structure split strategy
+    #[arg(long, value_enum, default_value_t)]
+    pub bvh: BvhSplit,
+
+    /// Stop after this long, like 90s, 20m or 1.5h, with however many samples are done by then.
+    /// --spp is still the most it will take
+    #[arg(long, value_parser = parse_duration)]
+    pub time_limit: Option<Duration>,
+
+    /// Write the image so far to the output file this often, like 30s or 5m
+    #[arg(long, value_parser = parse_duration)]
+    pub checkpoint: Option<Duration>,
+
+    /// Image file to draw the number of samples each pixel took in, brightest for the most
+    #[arg(long)]
+    pub heatmap: Option<PathBuf>,
+
+    /// Instead of rendering, print every step of every sample through pixel x,y as JSON
+    #[arg(long, value_parser = parse_pixel)]
+    pub trace_pixel: Option<(i32, i32)>,
+}
+
+#[derive(Args)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub camera: CameraArgs,
+}
+
+// Settings that override the scene's camera. Anything left out keeps the scene's value.
+#[derive(Args, Default)]
+pub struct CameraArgs {
+    /// Image width in pixels
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub width: Option<i32>,
+
+    /// Image aspect ratio, as a number like 1.5 or a ratio like 16:9
+    #[arg(long, value_parser = parse_aspect)]
+    pub aspect: Option<f32>,
+
+    /// Samples per pixel
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub spp: Option<i32>,
+
+    /// Maximum number of bounces per ray
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub max_depth: Option<i32>,
+
+    /// Image file to write; .png and .ppm pick the format unless --format is given
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// Image format to write [default: from the output file's extension]
+    #[arg(long, value_enum)]
+    pub format: Option<ImageFormat>,
+
+    /// Sample precision of OpenEXR output [default: half]
+    #[arg(long, value_enum)]
+    pub exr_precision: Option<ExrSamples>,
+
+    /// Lossless compression of OpenEXR output [default: zip]
+    #[arg(long, value_enum)]
+    pub exr_compression: Option<ExrPacking>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = parse_vfov)]
+    pub vfov: Option<f64>,
+
+    /// Camera position, as x,y,z
+    #[arg(long, value_parser = parse_dvec3, allow_hyphen_values = true)]
+    pub look_from: Option<DVec3>,
+
+    /// Point the camera looks at, as x,y,z
+    #[arg(long, value_parser = parse_dvec3, allow_hyphen_values = true)]
+    pub look_at: Option<DVec3>,
+
+    /// Camera-relative up direction, as x,y,z
+    #[arg(long, value_parser = parse_dvec3, allow_hyphen_values = true)]
+    pub look_up: Option<DVec3>,
+
+    /// Angle of the cone of rays through each pixel, in degrees; 0 disables depth of field
+    #[arg(long, value_parser = parse_defocus_angle)]
+    pub defocus_angle: Option<f64>,
+
+    /// Distance from the camera to the plane of perfect focus
+    #[arg(long, value_parser = parse_positive)]
+    pub focus_dist: Option<f64>,
+
+    /// How diffuse surfaces find the scene's lights
+    #[arg(long, value_enum)]
+    pub light_sampling: Option<LightStrategy>,
+
+    /// Color seen by rays that leave the scene, as r,g,b, or `sky` for the default gradient
+    #[arg(long, value_parser = parse_background)]
+    pub background: Option<Background>,
+
+    /// Order threads pick up tiles of the image in, or `rows` for one row at a time [default: spiral]
+    #[arg(long, value_enum)]
+    pub tile_order: Option<TileWalk>,
+
+    /// Width and height of the tiles in pixels [default: 16]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub tile_size: Option<u16>,
+
+    /// Stop sampling pixels once their noise is below this fraction of full brightness, like
+    /// 0.01. --spp becomes the most samples a pixel takes
+    #[arg(long, value_parser = parse_positive)]
+    pub adaptive: Option<f64>,
+
+    /// Samples every pixel takes before adaptive sampling judges it [default: 16]
+    #[arg(long, requires = "adaptive", value_parser = clap::value_parser!(i32).range(1..))]
+    pub min_spp: Option<i32>,
+}
+
+impl CameraArgs {
+    pub fn apply(&self, cam: &mut Camera) {
+        if let Some(width) = self.width {
+            cam.image_width = width;
+        }
+        if let Some(aspect) = self.aspect {
+            cam.aspect_ratio = aspect;
+        }
+        if let Some(spp) = self.spp {
+            cam.samples_per_pixel = spp;
+        }
+        if let Some(max_depth) = self.max_depth {
+            cam.max_depth = max_depth;
+        }
+        if let Some(output) = &self.output {
+            cam.output = output.clone();
+        }
+        if let Some(format) = self.format {
+            cam.output_format = Some(format.into());
+        }
+        // The EXR settings only apply once the output is known to be an EXR
+        if let Some(OutputFormat::Exr(mut options)) = cam.output_format() {
+            if let Some(precision) = self.exr_precision {
+                options.precision = precision.into();
+            }
+            if let Some(compression) = self.exr_compression {
+                options.compression = compression.into();
+            }
+            cam.output_format = Some(OutputFormat::Exr(options));
+        }
+        if let Some(vfov) = self.vfov {
+            cam.vfov = vfov;
+        }
+        if let Some(look_from) = self.look_from {
+            cam.look_from = look_from;
+        }
+        if let Some(look_at) = self.look_at {
+            cam.look_at = look_at;
+        }
+        if let Some(look_up) = self.look_up {
+            cam.look_up = look_up;
+        }
+        if let Some(defocus_angle) = self.defocus_angle {
+            cam.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            cam.focus_dist = focus_dist;
+        }
+        if let Some(light_sampling) = self.light_sampling {
+            cam.light_sampling = light_sampling.into();
+        }
+        if let Some(background) = self.background {
+            cam.background = background;
+        }
+        if let Some(threshold) = self.adaptive {
+            let adaptive = cam.adaptive.get_or_insert_with(Adaptive::default);
+            adaptive.threshold = threshold;
+        }
+        if let (Some(min_spp), Some(adaptive)) = (self.min_spp, &mut cam.adaptive) {
+            adaptive.min_samples = min_spp;
+        }
+        // A tile size on its own keeps rendering by rows if that's what the scene asked for
+        cam.schedule = match (self.tile_order, cam.schedule) {
+            (Some(TileWalk::Rows), _) | (None, Schedule::Rows) => Schedule::Rows,
+            (walk, current) => {
+                let (size, order) = match current {
+                    Schedule::Tiles { size, order } => (size, order),
+                    Schedule::Rows => (DEFAULT_TILE_SIZE, TileOrder::default()),
+                };
+                Schedule::Tiles {
+                    size: self.tile_size.map_or(size, usize::from),
+                    order: walk.and_then(TileWalk::order).unwrap_or(order),
+                }
+            }
+        };
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum BvhSplit {
+    Midpoint,
+    Median,
+    #[default]
+    Sah,
+}
+
+impl From<BvhSplit> for SplitStrategy {
+    fn from(split: BvhSplit) -> SplitStrategy {
+        match split {
+            BvhSplit::Midpoint => SplitStrategy::Midpoint,
+            BvhSplit::Median => SplitStrategy::Median,
+            BvhSplit::Sah => SplitStrategy::Sah,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum LightStrategy {
+    /// Only scattered rays that happen to hit a light
+    Bsdf,
+    /// Only shadow rays aimed at the lights
+    Lights,
+    /// Both, with multiple importance sampling
+    Mis,
+}
+
+impl From<LightStrategy> for LightSampling {
+    fn from(strategy: LightStrategy) -> LightSampling {
+        match strategy {
+            LightStrategy::Bsdf => LightSampling::Bsdf,
+            LightStrategy::Lights => LightSampling::Lights,
+            LightStrategy::Mis => LightSampling::Mis,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum TileWalk {
+    /// Whole rows, top to bottom, each finished before the next starts
+    Rows,
+    /// Tiles left to right, top to bottom
+    Scanline,
+    /// Tiles outwards from the middle
+    Spiral,
+    /// Tiles along a Hilbert curve
+    Hilbert,
+}
+
+impl TileWalk {
+    // The order of the tiles, or None when rendering by rows
+    fn order(self) -> Option<TileOrder> {
+        match self {
+            TileWalk::Rows => None,
+            TileWalk::Scanline => Some(TileOrder::Scanline),
+            TileWalk::Spiral => Some(TileOrder::Spiral),
+            TileWalk::Hilbert => Some(TileOrder::Hilbert),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ImageFormat {
+    /// Plain text PPM
+    P3,
+    /// Binary PPM
+    P6,
+    /// 8 bit PNG
+    Png,
+    /// 16 bit PNG
+    Png16,
+    /// Radiance RGBE
+    Hdr,
+    /// OpenEXR
+    Exr,
+}
+
+impl From<ImageFormat> for OutputFormat {
+    fn from(format: ImageFormat) -> OutputFormat {
+        match format {
+            ImageFormat::P3 => OutputFormat::PpmAscii,
+            ImageFormat::P6 => OutputFormat::PpmBinary,
+            ImageFormat::Png => OutputFormat::Png8,
+            ImageFormat::Png16 => OutputFormat::Png16,
+            ImageFormat::Hdr => OutputFormat::Hdr,
+            ImageFormat::Exr => OutputFormat::Exr(Default::default()),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ExrSamples {
+    Half,
+    Float,
+}
+
+impl From<ExrSamples> for ExrPrecision {
+    fn from(samples: ExrSamples) -> ExrPrecision {
+        match samples {
+            ExrSamples::Half => ExrPrecision::Half,
+            ExrSamples::Float => ExrPrecision::Float,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ExrPacking {
+    None,
+    Rle,
+    Zip,
+    Piz,
+}
+
+impl From<ExrPacking> for ExrCompression {
+    fn from(packing: ExrPacking) -> ExrCompression {
+        match packing {
+            ExrPacking::None => ExrCompression::None,
+            ExrPacking::Rle => ExrCompression::Rle,
+            ExrPacking::Zip => ExrCompression::Zip,
+            ExrPacking::Piz => ExrCompression::Piz,
+        }
+    }
+}
+
+fn parse_dvec3(s: &str) -> Result<DVec3, String> {
+    let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    let [x, y, z] = parts[..] else {
+        return Err(format!(
+            "expected three comma separated numbers, found `{s}`"
+        ));
+    };
+    let parse = |p: &str| {
+        p.parse::<f64>()
+            .map_err(|_| format!("`{p}` is not a number"))
+    };
+    Ok(DVec3::new(parse(x)?, parse(y)?, parse(z)?))
+}
+
+fn parse_pixel(s: &str) -> Result<(i32, i32), String> {
+    let Some((x, y)) = s.split_once(',') else {
+        return Err(format!("expected a pixel as x,y, found `{s}`"));
+    };
+    let parse = |p: &str| {
+        p.trim()
+            .parse::<i32>()
+            .ok()
+            .filter(|&n| n >= 0)
+            .ok_or_else(|| format!("`{p}` is not a pixel coordinate"))
+    };
+    Ok((parse(x)?, parse(y)?))
+}
+
+fn parse_background(s: &str) -> Result<Background, String> {
+    if s == "sky" {
+        return Ok(Background::Sky);
+    }
+    let color = parse_dvec3(s)?;
+    if color.min_element() < 0.0 {
+        return Err(format!("`{s}` has a negative component"));
+    }
+    Ok(Background::Color(color))
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(threshold) if threshold > 0.0 && threshold.is_finite() => Ok(threshold),
+        Ok(_) => Err(format!("`{s}` is not a positive number")),
+        Err(_) => Err(format!("`{s}` is not a number")),
+    }
+}
+
+fn parse_vfov(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(vfov) if vfov > 0.0 && vfov < 180.0 => Ok(vfov),
+        Ok(_) => Err(format!("`{s}` is not between 0 and 180 degrees")),
+        Err(_) => Err(format!("`{s}` is not a number")),
+    }
+}
+
+fn parse_defocus_angle(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(angle) if angle >= 0.0 && angle.is_finite() => Ok(angle),
+        Ok(_) => Err(format!("`{s}` is not an angle of 0 or more")),
+        Err(_) => Err(format!("`{s}` is not a number")),
+    }
+}
+
+// A number of seconds, minutes or hours, with an s, m or h after it. Bare numbers are seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit) = match s.strip_suffix(['s', 'm', 'h']) {
+        Some(number) => (number, &s[number.len()..]),
+        None => (s, "s"),
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{s}` is not a duration like 90s, 20m or 1.5h"))?;
+    let seconds = match unit {
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => value,
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("`{s}` is not a usable duration"))
+}
+
+fn parse_aspect(s: &str) -> Result<f32, String> {
+    let aspect = match s.split_once(':') {
+        Some((w, h)) => {
+            let w: f32 = w
+                .trim()
+                .parse()
+                .map_err(|_| format!("`{w}` is not a number"))?;
+            let h: f32 = h
+                .trim()
+                .parse()
+                .map_err(|_| format!("`{h}` is not a number"))?;
+            w / h
+        }
+        None => s.parse().map_err(|_| format!("`{s}` is not a number"))?,
+    };
+
+    if aspect > 0.0 && aspect.is_finite() {
+        Ok(aspect)
+    } else {
+        Err(format!("`{s}` is not a positive aspect ratio"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayrusting::output::ExrOptions;
+
+    #[test]
+    fn render_flags_map_onto_camera_test() {
+        let cli = Cli::try_parse_from([
+            "rayrusting",
+            "render",
+            "--width",
+            "64",
+            "--aspect",
+            "16:9",
+            "--spp",
+            "4",
+            "--max-depth",
+            "3",
+            "--output",
+            "out.ppm",
+            "--look-from",
+            "-1,2.5,3",
+            "--vfov",
+            "35",
+            "--background",
+            "0,0,0",
+            "--light-sampling",
+            "bsdf",
+            "--tile-order",
+            "hilbert",
+            "--tile-size",
+            "32",
+            "--adaptive",
+            "0.02",
+            "--min-spp",
+            "8",
+        ])
+        .unwrap();
+
+        let Some(Command::Render(args)) = cli.command else {
+            panic!("expected the render subcommand");
+        };
+
+        let mut cam = Camera::new();
+        args.camera.apply(&mut cam);
+
+        assert_eq!(cam.image_width, 64);
+        assert!((cam.aspect_ratio - 16.0 / 9.0).abs() < f32::EPSILON);
+        assert_eq!(cam.samples_per_pixel, 4);
+        assert_eq!(cam.max_depth, 3);
+        assert_eq!(cam.output, PathBuf::from("out.ppm"));
+        assert_eq!(cam.look_from, DVec3::new(-1.0, 2.5, 3.0));
+        assert_eq!(cam.vfov, 35.0);
+        assert_eq!(cam.background, Background::Color(DVec3::ZERO));
+        assert_eq!(cam.light_sampling, LightSampling::Bsdf);
+        assert_eq!(
+            cam.schedule,
+            Schedule::Tiles {
+                size: 32,
+                order: TileOrder::Hilbert
+            }
+        );
+        assert_eq!(
+            cam.adaptive,
+            Some(Adaptive {
+                min_samples: 8,
+                threshold: 0.02
+            })
+        );
+        // Untouched settings keep their defaults.
+        assert_eq!(cam.look_at, Camera::new().look_at);
+    }
+
+    #[test]
+    fn output_format_test() {
+        let camera_for = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["rayrusting", "render"].iter().chain(args)).unwrap();
+            let Some(Command::Render(args)) = cli.command else {
+                panic!("expected the render subcommand");
+            };
+            let mut cam = Camera::new();
+            args.camera.apply(&mut cam);
+            cam
+        };
+
+        assert_eq!(
+            camera_for(&["--output", "a.png"]).output_format(),
+            Some(OutputFormat::Png8)
+        );
+        assert_eq!(
+            camera_for(&["--output", "a.png", "--format", "png16"]).output_format(),
+            Some(OutputFormat::Png16)
+        );
+        assert_eq!(
+            camera_for(&["--output", "a.ppm"]).output_format(),
+            Some(OutputFormat::PpmAscii)
+        );
+        assert_eq!(
+            camera_for(&["--output", "a.ppm", "--format", "p6"]).output_format(),
+            Some(OutputFormat::PpmBinary)
+        );
+        assert_eq!(
+            camera_for(&["--output", "a.exr", "--exr-precision", "float"]).output_format(),
+            Some(OutputFormat::Exr(ExrOptions {
+                precision: ExrPrecision::Float,
+                compression: ExrCompression::Zip,
+            }))
+        );
+        assert_eq!(
+            camera_for(&["--format", "exr", "--exr-compression", "none"]).output_format(),
+            Some(OutputFormat::Exr(ExrOptions {
+                precision: ExrPrecision::Half,
+                compression: ExrCompression::None,
+            }))
+        );
+        // EXR settings don't turn other formats into EXR
+        assert_eq!(
+            camera_for(&["--output", "a.hdr", "--exr-compression", "rle"]).output_format(),
+            Some(OutputFormat::Hdr)
+        );
+        assert_eq!(camera_for(&["--output", "a.tga"]).output_format(), None);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected_test() {
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--width", "0"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--aspect", "4:0"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--look-at", "1,2"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--threads", "0"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--background", "-1,0,0"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--time-limit", "5d"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--adaptive", "0"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--trace-pixel", "3"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--trace-pixel", "-1,2"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "-q", "-v", "info"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--checkpoint", "-1m"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--min-spp", "8"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--vfov", "180"]).is_err());
+        assert!(Cli::try_parse_from(["rayrusting", "render", "--focus-dist", "0"]).is_err());
+
+        // The message names the flag that was wrong.
+        let Err(err) = Cli::try_parse_from(["rayrusting", "render", "--defocus-angle=-1"]) else {
+            panic!("a negative defocus angle was accepted");
+        };
+        assert!(err.to_string().contains("--defocus-angle"), "{err}");
+    }
+
+    #[test]
+    fn duration_test() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2.5s"), Ok(Duration::from_millis(2500)));
+        assert_eq!(parse_duration("20m"), Ok(Duration::from_secs(1200)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("m").is_err());
+    }
+
+    #[test]
+    fn cli_definition_test() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
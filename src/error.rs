@@ -0,0 +1,99 @@
+// Everything that can go wrong building a scene or rendering it, for callers who'd rather handle
+// one error type than one per loader.
+
+use crate::obj::ObjError;
+use crate::scene::SceneError;
+use crate::stl::StlError;
+use crate::texture::TextureError;
+use crate::volume::VolumeError;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // The rendered image couldn't be written to `path`
+    Write { path: PathBuf, source: io::Error },
+    // The output path's extension doesn't name an image format
+    UnknownFormat(PathBuf),
+    // An object that can't be placed in a scene, such as one whose bounds aren't numbers
+    InvalidObject(String),
+    Scene(SceneError),
+    Texture(TextureError),
+    Obj(ObjError),
+    Stl(StlError),
+    Volume(VolumeError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Write { path, source } => write!(f, "{}: {source}", path.display()),
+            Error::UnknownFormat(path) => write!(
+                f,
+                "{}: unknown image format; use a .png, .ppm, .hdr or .exr extension",
+                path.display()
+            ),
+            Error::InvalidObject(message) => write!(f, "invalid object: {message}"),
+            Error::Scene(e) => write!(f, "{e}"),
+            Error::Texture(e) => write!(f, "{e}"),
+            Error::Obj(e) => write!(f, "{e}"),
+            Error::Stl(e) => write!(f, "{e}"),
+            Error::Volume(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Write { source: e, .. } => Some(e),
+            Error::UnknownFormat(_) | Error::InvalidObject(_) => None,
+            Error::Scene(e) => Some(e),
+            Error::Texture(e) => Some(e),
+            Error::Obj(e) => Some(e),
+            Error::Stl(e) => Some(e),
+            Error::Volume(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<SceneError> for Error {
+    fn from(e: SceneError) -> Error {
+        Error::Scene(e)
+    }
+}
+
+impl From<TextureError> for Error {
+    fn from(e: TextureError) -> Error {
+        Error::Texture(e)
+    }
+}
+
+impl From<ObjError> for Error {
+    fn from(e: ObjError) -> Error {
+        Error::Obj(e)
+    }
+}
+
+impl From<StlError> for Error {
+    fn from(e: StlError) -> Error {
+        Error::Stl(e)
+    }
+}
+
+impl From<VolumeError> for Error {
+    fn from(e: VolumeError) -> Error {
+        Error::Volume(e)
+    }
+}
@@ -1,19 +1,44 @@
 use crate::aabb::AABB;
+use crate::error::Error;
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::utils;
 use glam::DVec3;
+use rand::Rng;
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct HitRecord {
-    pub p: DVec3,         // point of hit
-    pub normal: DVec3,    // normal vector at point of hit
-    pub t: f64,           // distance traveled to hit
-    pub front_face: bool, // did ray intersect the front face of the object
-    pub mat: Material,    // what material was hit
+// Borrows the material from the object that was hit, so finding the closest hit never copies
+// one.
+#[derive(Debug, Clone, Copy)]
+pub struct HitRecord<'a> {
+    pub p: DVec3,                // point of hit
+    pub normal: DVec3,           // shading normal vector at point of hit
+    pub geometric_normal: DVec3, // true surface normal, on the same side as `normal`
+    pub t: f64,                  // distance traveled to hit
+    pub u: f64,                  // surface coordinates of the hit, barycentric for triangles
+    pub v: f64,                  //
+    pub front_face: bool,        // did ray intersect the front face of the object
+    pub mat: &'a Material,       // what material was hit
 }
 
-impl HitRecord {
+impl Default for HitRecord<'_> {
+    fn default() -> Self {
+        static DEFAULT: Material = Material::Default;
+        HitRecord {
+            p: DVec3::ZERO,
+            normal: DVec3::ZERO,
+            geometric_normal: DVec3::ZERO,
+            t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: false,
+            mat: &DEFAULT,
+        }
+    }
+}
+
+impl HitRecord<'_> {
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: DVec3) {
         // Sets the hit record normal vector.
         // NOTE: the parameter `outward_normal` is assumed to have unit length.
@@ -24,48 +49,122 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        self.geometric_normal = self.normal;
     }
 }
 
-pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, ray_t: Interval, debug: bool) -> Option<HitRecord>;
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>>;
     fn bounding_box(&self) -> Option<AABB>;
+
+    // Probability density, per unit solid angle, that `random` picks `direction` from `origin`.
+    // Objects that can't be sampled as lights keep the default of zero.
+    fn pdf_value(&self, _origin: DVec3, _direction: DVec3) -> f64 {
+        0.0
+    }
+
+    // A direction from `origin` towards a random point on the object, for sampling it as a light.
+    fn random(&self, _origin: DVec3) -> DVec3 {
+        DVec3::X
+    }
 }
 
 #[derive(Default)]
 pub struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
     bbox: AABB,
+    // Whether any object has no bounding box, such as an infinite plane, leaving the list
+    // without one too
+    unbounded: bool,
+}
+
+impl<T: Hittable + ?Sized> Hittable for Box<T> {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        (**self).bounding_box()
+    }
+
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: DVec3) -> DVec3 {
+        (**self).random(origin)
+    }
+}
+
+// Lets instances share one copy of an object, such as a mesh, instead of each holding their own.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        (**self).bounding_box()
+    }
+
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: DVec3) -> DVec3 {
+        (**self).random(origin)
+    }
 }
 
 impl HittableList {
-    pub fn add(&mut self, obj: Box<dyn Hittable>) {
-        self.bbox = AABB::from_boxes(&self.bbox, &obj.bounding_box().unwrap());
+    pub fn new() -> HittableList {
+        HittableList::default()
+    }
+
+    // Adds an object to the list. Objects without a bounding box are fine, but one whose bounds
+    // aren't numbers is refused, since it would spoil the bounds of the list and any BVH built
+    // from it.
+    pub fn add(&mut self, obj: Box<dyn Hittable>) -> Result<(), Error> {
+        match obj.bounding_box() {
+            Some(bbox) if bbox.has_nan() => {
+                return Err(Error::InvalidObject(format!(
+                    "bounding box {bbox:?} isn't made of numbers"
+                )));
+            }
+            Some(bbox) => self.bbox = AABB::from_boxes(&self.bbox, &bbox),
+            None => self.unbounded = true,
+        }
         self.objects.push(obj);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    // Hands over ownership of the objects, e.g. to build an acceleration structure from them.
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        self.objects
     }
 }
 
 impl Hittable for HittableList {
     // Casts a Ray into a scene, and returns the closest HitRecord
-    fn hit(&self, r: &Ray, ray_t: Interval, debug: bool) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         // Set the water line to the max distance
         let mut closest_so_far = ray_t.max;
-        if debug {
-            println!("hittable::hit::closest_so_far::{:?}", closest_so_far);
-            println!("hittable::hit::ray_t::{:?}", ray_t);
-        }
         // Initialize a hit_record to return
         let mut hit_record: Option<HitRecord> = None;
 
         // Loop through all objects in the scene
         for object in self.objects.iter() {
             // If an object returns that there is a valid hit between the minimum distance and the water line
-            if let Some(temp_rec) = object.hit(r, Interval::new(ray_t.min, closest_so_far), debug) {
+            if let Some(temp_rec) = object.hit(r, Interval::new(ray_t.min, closest_so_far)) {
                 // Update the water line
                 closest_so_far = temp_rec.t;
-                if debug {
-                    println!("hittable::hit::closest_so_far(loop)::{:?}", closest_so_far);
-                }
 
                 // Update hit_record with the HitRecord of the hit
                 hit_record = Some(temp_rec);
@@ -75,6 +174,24 @@ impl Hittable for HittableList {
     }
 
     fn bounding_box(&self) -> Option<AABB> {
-        Some(self.bbox)
+        (!self.unbounded).then_some(self.bbox)
+    }
+
+    // Sampling picks one of the objects uniformly, so the density is the average of theirs.
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: DVec3) -> DVec3 {
+        let index = utils::rng().gen_range(0..self.objects.len());
+        self.objects[index].random(origin)
     }
 }
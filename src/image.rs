@@ -0,0 +1,327 @@
+use crate::output::{self, OutputFormat};
+use glam::DVec3;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+// A rendered frame held in memory, before any encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    // Linear, unclamped colors in row-major order starting from the top left
+    pub pixels: Vec<DVec3>,
+    // How many samples went into each pixel, in the same order as the pixels. None when every
+    // pixel got the same number.
+    pub sample_counts: Option<Vec<u32>>,
+}
+
+impl Image {
+    // A black image of the given size.
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![DVec3::ZERO; width * height],
+            sample_counts: None,
+        }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<DVec3>) -> Image {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "a {width}x{height} image needs {} pixels",
+            width * height
+        );
+        Image {
+            width,
+            height,
+            pixels,
+            sample_counts: None,
+        }
+    }
+
+    // The color of the pixel in column x of row y, counting from the top left.
+    pub fn get(&self, x: usize, y: usize) -> DVec3 {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: DVec3) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+
+    // The number of samples behind the pixel at (x, y), if it's known.
+    pub fn sample_count(&self, x: usize, y: usize) -> Option<u32> {
+        let index = self.index(x, y);
+        self.sample_counts.as_ref().map(|counts| counts[index])
+    }
+
+    // A picture of how many samples each pixel got, from black for none through purple and
+    // orange to pale yellow for the most any pixel got. Every pixel is the same when the counts
+    // weren't kept.
+    pub fn sample_heatmap(&self) -> Image {
+        // Display colors, squared to undo the gamma the output will apply
+        const RAMP: [DVec3; 4] = [
+            DVec3::new(0.0, 0.0, 0.0),
+            DVec3::new(0.47, 0.11, 0.43),
+            DVec3::new(0.93, 0.41, 0.13),
+            DVec3::new(0.99, 1.0, 0.64),
+        ];
+        let color = |t: f64| {
+            let position = t.clamp(0.0, 1.0) * (RAMP.len() - 1) as f64;
+            let i = (position as usize).min(RAMP.len() - 2);
+            let c = RAMP[i].lerp(RAMP[i + 1], position - i as f64);
+            c * c
+        };
+
+        let pixels = match &self.sample_counts {
+            Some(counts) => {
+                let most = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+                counts.iter().map(|&n| color(n as f64 / most)).collect()
+            }
+            None => vec![color(1.0); self.pixels.len()],
+        };
+        Image::from_pixels(self.width, self.height, pixels)
+    }
+
+    // Applies f to every pixel, for post-processing like exposure adjustments.
+    pub fn map(&mut self, f: impl Fn(DVec3) -> DVec3) {
+        for pixel in &mut self.pixels {
+            *pixel = f(*pixel);
+        }
+    }
+
+    pub fn write(&self, path: &Path, format: OutputFormat) -> std::io::Result<()> {
+        output::write_image(path, format, self)
+    }
+
+    pub fn encode(&self, writer: &mut impl Write, format: OutputFormat) -> std::io::Result<()> {
+        output::encode(writer, format, self)
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({x}, {y}) is outside the {}x{} image",
+            self.width,
+            self.height
+        );
+        y * self.width + x
+    }
+}
+
+// Sums of samples that any number of threads can add to at once without locking, as long as
+// they stick to different pixels. Each value is kept as the bits of an f64 in an atomic.
+#[derive(Debug)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    sums: Vec<[AtomicU64; 3]>,
+    counts: Vec<AtomicU32>,
+    // The running mean and sum of squared differences from it (Welford's M2) of each pixel's
+    // sample luminances, for judging how noisy it still is
+    luminance: Vec<[AtomicU64; 2]>,
+}
+
+impl Framebuffer {
+    // A frame with no samples in it yet.
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        // All zero bits is 0.0
+        Framebuffer {
+            width,
+            height,
+            sums: (0..width * height).map(|_| Default::default()).collect(),
+            counts: (0..width * height).map(|_| AtomicU32::new(0)).collect(),
+            luminance: (0..width * height).map(|_| Default::default()).collect(),
+        }
+    }
+
+    // Adds one sample to the pixel at (x, y). Only one thread at a time may add to any one pixel.
+    pub fn add_sample(&self, x: usize, y: usize, color: DVec3) {
+        let index = self.index(x, y);
+        for (channel, value) in self.sums[index].iter().zip(color.to_array()) {
+            store(channel, load(channel) + value);
+        }
+        let count = self.counts[index].fetch_add(1, Ordering::Relaxed) + 1;
+
+        let [mean, m2] = &self.luminance[index];
+        let l = luminance(color);
+        let delta = l - load(mean);
+        store(mean, load(mean) + delta / count as f64);
+        store(m2, load(m2) + delta * (l - load(mean)));
+    }
+
+    // The mean of the samples in the pixel at (x, y), or black before it has any.
+    pub fn get(&self, x: usize, y: usize) -> DVec3 {
+        let index = self.index(x, y);
+        let sum = self.sums[index].each_ref().map(load);
+        match self.counts[index].load(Ordering::Relaxed) {
+            0 => DVec3::ZERO,
+            count => DVec3::from_array(sum) * (1.0 / count as f64),
+        }
+    }
+
+    pub fn sample_count(&self, x: usize, y: usize) -> u32 {
+        self.counts[self.index(x, y)].load(Ordering::Relaxed)
+    }
+
+    // How far the pixel at (x, y) is likely to be from its converged value, as the standard error
+    // of its mean luminance carried through the output's gamma of 2. That makes it a fraction of
+    // full brightness on screen, so noise counts for less in bright pixels than dark ones, as it
+    // looks. Infinite until there are two samples to compare.
+    pub fn error(&self, x: usize, y: usize) -> f64 {
+        let index = self.index(x, y);
+        let count = self.counts[index].load(Ordering::Relaxed) as f64;
+        if count < 2.0 {
+            return f64::INFINITY;
+        }
+        let [mean, m2] = self.luminance[index].each_ref().map(load);
+
+        let variance = m2 / (count - 1.0);
+        let standard_error = (variance / count).sqrt();
+        if standard_error == 0.0 {
+            return 0.0;
+        }
+        // The slope of sqrt(l) is 1 / (2 sqrt(l))
+        standard_error / (2.0 * mean.max(f64::MIN_POSITIVE).sqrt())
+    }
+
+    // A copy of the frame as it is now. Call it once the writers are done for a finished image.
+    pub fn to_image(&self) -> Image {
+        let pixels = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.get(x, y))
+            .collect();
+        let mut image = Image::from_pixels(self.width, self.height, pixels);
+
+        let counts: Vec<u32> = self
+            .counts
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+        if counts.iter().any(|&c| c != counts[0]) {
+            image.sample_counts = Some(counts);
+        }
+        image
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({x}, {y}) is outside the {}x{} frame",
+            self.width,
+            self.height
+        );
+        y * self.width + x
+    }
+}
+
+// Relative luminance of a linear color, with the Rec. 709 weights.
+pub fn luminance(color: DVec3) -> f64 {
+    color.dot(DVec3::new(0.2126, 0.7152, 0.0722))
+}
+
+fn load(value: &AtomicU64) -> f64 {
+    f64::from_bits(value.load(Ordering::Relaxed))
+}
+
+fn store(value: &AtomicU64, x: f64) {
+    value.store(x.to_bits(), Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_access_test() {
+        let mut image = Image::new(3, 2);
+        image.set(2, 1, DVec3::ONE);
+
+        assert_eq!(image.get(2, 1), DVec3::ONE);
+        assert_eq!(image.pixels[5], DVec3::ONE);
+        assert_eq!(image.get(0, 0), DVec3::ZERO);
+        assert_eq!(image.sample_count(2, 1), None);
+
+        image.map(|c| c * 2.0);
+        assert_eq!(image.get(2, 1), DVec3::splat(2.0));
+    }
+
+    #[test]
+    fn framebuffer_test() {
+        let frame = Framebuffer::new(4, 3);
+        // Threads writing disjoint pixels need no locks.
+        std::thread::scope(|scope| {
+            for y in 0..3 {
+                let frame = &frame;
+                scope.spawn(move || {
+                    for x in 0..4 {
+                        frame.add_sample(x, y, DVec3::new(x as f64, y as f64, -1.5));
+                    }
+                });
+            }
+        });
+
+        let image = frame.to_image();
+        assert_eq!((image.width, image.height), (4, 3));
+        assert_eq!(image.get(3, 2), DVec3::new(3.0, 2.0, -1.5));
+        assert_eq!(image.sample_counts, None);
+
+        // Pixels average whatever they've been given, and the counts show when they differ.
+        for _ in 0..3 {
+            frame.add_sample(1, 0, DVec3::new(1.0, 2.0 / 3.0, 0.5 / 3.0));
+        }
+        assert!(frame
+            .get(1, 0)
+            .abs_diff_eq(DVec3::new(1.0, 0.5, -0.25), 1e-15));
+        assert_eq!(frame.sample_count(1, 0), 4);
+        assert_eq!(frame.to_image().sample_count(1, 0), Some(4));
+        assert_eq!(frame.to_image().sample_count(0, 0), Some(1));
+    }
+
+    #[test]
+    fn error_test() {
+        let frame = Framebuffer::new(2, 1);
+
+        // Samples alternating between 0 and 2 have a mean of 1 and a variance of about 1, so after
+        // n of them the mean is off by about 1 / sqrt(n), which halves through the gamma.
+        for i in 0..400 {
+            frame.add_sample(0, 0, DVec3::splat((i % 2 * 2) as f64));
+        }
+        let expected = (400.0f64 / 399.0 / 400.0).sqrt() / 2.0;
+        assert!(
+            (frame.error(0, 0) - expected).abs() < 1e-12,
+            "{}",
+            frame.error(0, 0)
+        );
+
+        // A constant pixel has converged, but one sample says nothing.
+        frame.add_sample(1, 0, DVec3::splat(0.3));
+        assert_eq!(frame.error(1, 0), f64::INFINITY);
+        frame.add_sample(1, 0, DVec3::splat(0.3));
+        assert_eq!(frame.error(1, 0), 0.0);
+    }
+
+    #[test]
+    fn sample_heatmap_test() {
+        let mut image = Image::new(3, 1);
+        let uniform = image.sample_heatmap();
+        assert!(uniform.pixels.iter().all(|&c| c == uniform.pixels[0]));
+
+        image.sample_counts = Some(vec![0, 8, 16]);
+        let heatmap = image.sample_heatmap();
+        assert_eq!(heatmap.get(0, 0), DVec3::ZERO);
+        assert_eq!(heatmap.get(2, 0), uniform.get(0, 0));
+        // Brighter for more samples
+        let middle = heatmap.get(1, 0);
+        assert!(middle.max_element() > 0.0 && middle.max_element() < 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_bounds_test() {
+        Image::new(3, 2).get(0, 2);
+    }
+}
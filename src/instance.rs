@@ -0,0 +1,407 @@
+// Placing an object somewhere other than where it was built. Each wrapper moves the ray into the
+// object's own space, intersects it there, and moves the hit back out, so the object itself is
+// never copied. Wrap an `Arc` to place one object, such as a mesh, in several spots at once.
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use glam::{DMat3, DMat4, DVec3};
+use std::sync::Arc;
+
+// Moves an object by a fixed offset.
+pub struct Translate<T: Hittable = Arc<dyn Hittable>> {
+    object: T,
+    offset: DVec3,
+    bbox: Option<AABB>,
+}
+
+impl<T: Hittable> Translate<T> {
+    pub fn new(object: T, offset: DVec3) -> Translate<T> {
+        let bbox = object
+            .bounding_box()
+            .map(|bbox| transform_box(&bbox, &DMat4::from_translation(offset)));
+        Translate {
+            object,
+            offset,
+            bbox,
+        }
+    }
+}
+
+impl<T: Hittable> Hittable for Translate<T> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let offset_r = Ray {
+            origin: r.origin - self.offset,
+            ..*r
+        };
+        let mut rec = self.object.hit(&offset_r, ray_t)?;
+        rec.p += self.offset;
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        self.object.pdf_value(origin - self.offset, direction)
+    }
+
+    fn random(&self, origin: DVec3) -> DVec3 {
+        self.object.random(origin - self.offset)
+    }
+}
+
+// Turns an object about the y axis through the origin, counter-clockwise seen from above.
+pub struct RotateY<T: Hittable = Arc<dyn Hittable>> {
+    object: T,
+    sin_theta: f64,
+    cos_theta: f64,
+    bbox: Option<AABB>,
+}
+
+impl<T: Hittable> RotateY<T> {
+    pub fn new(object: T, degrees: f64) -> RotateY<T> {
+        let radians = degrees.to_radians();
+        let bbox = object
+            .bounding_box()
+            .map(|bbox| transform_box(&bbox, &DMat4::from_rotation_y(radians)));
+        RotateY {
+            object,
+            sin_theta: radians.sin(),
+            cos_theta: radians.cos(),
+            bbox,
+        }
+    }
+
+    // From object space out to world space
+    fn rotate(&self, v: DVec3) -> DVec3 {
+        DVec3::new(
+            self.cos_theta * v.x + self.sin_theta * v.z,
+            v.y,
+            -self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+
+    // From world space into object space
+    fn unrotate(&self, v: DVec3) -> DVec3 {
+        DVec3::new(
+            self.cos_theta * v.x - self.sin_theta * v.z,
+            v.y,
+            self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+}
+
+impl<T: Hittable> Hittable for RotateY<T> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let rotated_r = Ray {
+            origin: self.unrotate(r.origin),
+            direction: self.unrotate(r.direction),
+            ..*r
+        };
+        let mut rec = self.object.hit(&rotated_r, ray_t)?;
+        rec.p = self.rotate(rec.p);
+        rec.normal = self.rotate(rec.normal);
+        rec.geometric_normal = self.rotate(rec.geometric_normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bbox
+    }
+
+    // Rotations keep solid angles the same, so the object's own density carries over.
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        self.object
+            .pdf_value(self.unrotate(origin), self.unrotate(direction))
+    }
+
+    fn random(&self, origin: DVec3) -> DVec3 {
+        self.rotate(self.object.random(self.unrotate(origin)))
+    }
+}
+
+// Places an object with any invertible affine transform, such as a combination of scaling,
+// rotation and translation.
+pub struct Transform<T: Hittable = Arc<dyn Hittable>> {
+    object: T,
+    // Object space to world space, and back again
+    matrix: DMat4,
+    inverse: DMat4,
+    // Carries normals out to world space; the inverse transpose of the matrix's linear part
+    normal_matrix: DMat3,
+    bbox: Option<AABB>,
+}
+
+impl<T: Hittable> Transform<T> {
+    // Returns None if the matrix can't be inverted, as when it scales some axis to nothing.
+    pub fn new(object: T, matrix: DMat4) -> Option<Transform<T>> {
+        let det = matrix.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let inverse = matrix.inverse();
+        let bbox = object
+            .bounding_box()
+            .map(|bbox| transform_box(&bbox, &matrix));
+
+        Some(Transform {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: DMat3::from_mat4(inverse).transpose(),
+            bbox,
+        })
+    }
+
+    pub fn matrix(&self) -> DMat4 {
+        self.matrix
+    }
+
+    // How much the transform shrinks the solid angle around a unit direction `object_direction`
+    // on its way out to world space. Densities over directions grow by the same factor.
+    fn solid_angle_scale(&self, object_direction: DVec3) -> f64 {
+        let linear = DMat3::from_mat4(self.matrix);
+        linear.mul_vec3(object_direction).length().powi(3) / linear.determinant().abs()
+    }
+}
+
+impl<T: Hittable> Hittable for Transform<T> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        // The direction isn't renormalized, so t means the same thing in both spaces.
+        let object_r = Ray {
+            origin: self.inverse.transform_point3(r.origin),
+            direction: self.inverse.transform_vector3(r.direction),
+            ..*r
+        };
+        let mut rec = self.object.hit(&object_r, ray_t)?;
+        rec.p = self.matrix.transform_point3(rec.p);
+        rec.normal = self.normal_matrix.mul_vec3(rec.normal).normalize();
+        rec.geometric_normal = self
+            .normal_matrix
+            .mul_vec3(rec.geometric_normal)
+            .normalize();
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        let object_direction = self.inverse.transform_vector3(direction);
+        let pdf = self
+            .object
+            .pdf_value(self.inverse.transform_point3(origin), object_direction);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+        pdf * self.solid_angle_scale(object_direction.normalize())
+    }
+
+    fn random(&self, origin: DVec3) -> DVec3 {
+        let object_direction = self.object.random(self.inverse.transform_point3(origin));
+        self.matrix.transform_vector3(object_direction)
+    }
+}
+
+// The box around all eight corners of `bbox` once they've been transformed.
+fn transform_box(bbox: &AABB, matrix: &DMat4) -> AABB {
+    let mut result = AABB::new();
+    for i in 0..8 {
+        let corner = DVec3::new(
+            if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+            if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+            if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+        );
+        let p = matrix.transform_point3(corner);
+        result = AABB::from_boxes(&result, &AABB::from_points(p, p));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::mesh::{MeshData, MeshFace, TriangleMesh};
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+    use crate::utils::{self, random_dvec3_unit};
+    use glam::DQuat;
+    use std::f64;
+
+    fn forward() -> Interval {
+        Interval::new(0.001, f64::INFINITY)
+    }
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new_stationary(DVec3::ZERO, 1.0, Material::default())
+    }
+
+    fn assert_close(a: DVec3, b: DVec3) {
+        assert!((a - b).length() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn translate_test() {
+        let moved = Translate::new(unit_sphere(), DVec3::new(0.0, 0.0, -5.0));
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+        let rec = moved.hit(&r, forward()).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert_close(rec.p, DVec3::new(0.0, 0.0, -4.0));
+        assert_close(rec.normal, DVec3::Z);
+
+        let bbox = moved.bounding_box().unwrap();
+        assert!((bbox.z.min + 6.0).abs() < 1e-9 && (bbox.z.max + 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rotate_y_test() {
+        // A quad facing +z, turned a quarter of the way around to face +x
+        let quad = Quad::new(
+            DVec3::new(-1.0, -1.0, 0.0),
+            2.0 * DVec3::X,
+            2.0 * DVec3::Y,
+            Material::default(),
+        );
+        let turned = RotateY::new(quad, 90.0);
+
+        let r = Ray::with_direction(DVec3::new(3.0, 0.5, 0.5), -DVec3::X);
+        let rec = turned.hit(&r, forward()).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert_close(rec.p, DVec3::new(0.0, 0.5, 0.5));
+        assert_close(rec.normal, DVec3::X);
+        assert!(rec.front_face);
+
+        let bbox = turned.bounding_box().unwrap();
+        assert!(bbox.x.size() < 0.01);
+        assert!((bbox.z.min + 1.0).abs() < 1e-9 && (bbox.z.max - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transform_matches_rotate_y_test() {
+        let quad = || {
+            Quad::new(
+                DVec3::new(-1.0, -1.0, 0.5),
+                2.0 * DVec3::X,
+                2.0 * DVec3::Y,
+                Material::default(),
+            )
+        };
+        let rotated = RotateY::new(quad(), 30.0);
+        let transformed =
+            Transform::new(quad(), DMat4::from_rotation_y(30f64.to_radians())).unwrap();
+
+        for _ in 0..200 {
+            let r = Ray::with_direction(4.0 * random_dvec3_unit(), random_dvec3_unit());
+            match (rotated.hit(&r, forward()), transformed.hit(&r, forward())) {
+                (None, None) => {}
+                (Some(a), Some(b)) => {
+                    assert!((a.t - b.t).abs() < 1e-9);
+                    assert_close(a.p, b.p);
+                    assert_close(a.normal, b.normal);
+                    assert_eq!(a.front_face, b.front_face);
+                }
+                _ => panic!("only one of them hit"),
+            }
+        }
+    }
+
+    #[test]
+    fn scaled_transform_test() {
+        // Stretched into an ellipsoid twice as tall as it is wide
+        let matrix = DMat4::from_scale_rotation_translation(
+            DVec3::new(1.0, 2.0, 1.0),
+            DQuat::IDENTITY,
+            DVec3::new(0.0, 0.0, -5.0),
+        );
+        let ellipsoid = Transform::new(unit_sphere(), matrix).unwrap();
+
+        let r = Ray::with_direction(DVec3::new(0.0, 10.0, -5.0), -DVec3::Y);
+        let rec = ellipsoid.hit(&r, forward()).unwrap();
+        assert!((rec.t - 8.0).abs() < 1e-9);
+        assert_close(rec.p, DVec3::new(0.0, 2.0, -5.0));
+        assert_close(rec.normal, DVec3::Y);
+
+        // Off the axes the normal follows the gradient of x^2 + y^2 / 4, rather than just being
+        // stretched along with the sphere.
+        let r = Ray::with_direction(DVec3::new(0.5, 10.0, -5.0), -DVec3::Y);
+        let rec = ellipsoid.hit(&r, forward()).unwrap();
+        assert_close(
+            rec.normal,
+            DVec3::new(rec.p.x, rec.p.y / 4.0, 0.0).normalize(),
+        );
+
+        let bbox = ellipsoid.bounding_box().unwrap();
+        assert!((bbox.y.max - 2.0).abs() < 1e-9 && (bbox.x.max - 1.0).abs() < 1e-9);
+
+        let flat = DMat4::from_scale(DVec3::new(1.0, 0.0, 1.0));
+        assert!(Transform::new(unit_sphere(), flat).is_none());
+    }
+
+    #[test]
+    fn transformed_light_pdf_test() {
+        // A fixed stream, so the test can't fail by chance.
+        utils::reseed(1, 0, 0);
+        let matrix = DMat4::from_scale_rotation_translation(
+            DVec3::new(0.5, 2.0, 1.0),
+            DQuat::from_rotation_z(0.3),
+            DVec3::new(0.0, 1.5, 0.0),
+        );
+        let light = Transform::new(
+            Quad::new(
+                DVec3::new(-1.0, 0.0, -1.0),
+                2.0 * DVec3::X,
+                2.0 * DVec3::Z,
+                Material::default(),
+            ),
+            matrix,
+        )
+        .unwrap();
+        let origin = DVec3::ZERO;
+
+        for _ in 0..100 {
+            let direction = light.random(origin);
+            assert!(light.pdf_value(origin, direction) > 0.0);
+        }
+
+        // The density integrates to one over the sphere of directions.
+        let samples = 200_000;
+        let total: f64 = (0..samples)
+            .map(|_| light.pdf_value(origin, random_dvec3_unit()))
+            .sum();
+        let integral = total / samples as f64 * 4.0 * f64::consts::PI;
+        assert!((integral - 1.0).abs() < 0.05, "{integral}");
+    }
+
+    #[test]
+    fn shared_mesh_test() {
+        let data = MeshData {
+            positions: vec![
+                DVec3::new(-1.0, -1.0, 0.0),
+                DVec3::new(1.0, -1.0, 0.0),
+                DVec3::new(0.0, 1.0, 0.0),
+            ],
+            faces: vec![MeshFace {
+                positions: [0, 1, 2],
+                normals: None,
+                texcoords: None,
+            }],
+            ..Default::default()
+        };
+        let mesh: Arc<dyn Hittable> = Arc::new(TriangleMesh::new(data, Material::default()));
+
+        let left = Translate::new(mesh.clone(), DVec3::new(-3.0, 0.0, -2.0));
+        let right = Translate::new(mesh.clone(), DVec3::new(3.0, 0.0, -2.0));
+        assert_eq!(Arc::strong_count(&mesh), 3);
+
+        let r = Ray::with_direction(DVec3::new(-3.0, 0.0, 0.0), -DVec3::Z);
+        assert!(left.hit(&r, forward()).is_some());
+        assert!(right.hit(&r, forward()).is_none());
+        let r = Ray::with_direction(DVec3::new(3.0, 0.0, 0.0), -DVec3::Z);
+        assert!(right.hit(&r, forward()).is_some());
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -23,7 +23,7 @@ impl Interval {
         }
     }
 
-    pub fn _size(&self) -> f64 {
+    pub fn size(&self) -> f64 {
         self.max - self.min
     }
     pub fn _contains(&self, x: f64) -> bool {
@@ -32,6 +32,11 @@ impl Interval {
     pub fn surrounds(&self, x: f64) -> bool {
         self.min < x && x < self.max
     }
+    // Grows the interval by `delta` in total, split evenly between both ends.
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::new(self.min - padding, self.max + padding)
+    }
     pub fn clamp(&self, x: f64) -> f64 {
         if x < self.min {
             self.min
@@ -43,11 +48,18 @@ impl Interval {
     }
 }
 
+// Intervals are empty by default, so they can be grown from nothing with `from_intervals`.
+impl Default for Interval {
+    fn default() -> Interval {
+        Interval::empty()
+    }
+}
+
 pub const _EMPTY: Interval = Interval {
     min: f64::INFINITY,
     max: f64::NEG_INFINITY,
 };
-pub const _UNIVERSE: Interval = Interval {
+pub const UNIVERSE: Interval = Interval {
     min: f64::NEG_INFINITY,
     max: f64::INFINITY,
 };
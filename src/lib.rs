@@ -0,0 +1,65 @@
+// A path tracer following the Ray Tracing in One Weekend books.
+//
+// Build a world out of shapes and materials, point a `Camera` at it and render it to an `Image`
+// or straight to a file:
+//
+//     use rayrusting::{Camera, HittableList, Material, Sphere};
+//     use glam::DVec3;
+//
+//     let mut world = HittableList::new();
+//     world.add(Box::new(Sphere::new_stationary(
+//         DVec3::new(0.0, 0.0, -1.0),
+//         0.5,
+//         Material::lambertian(DVec3::splat(0.5)),
+//     )))?;
+//
+//     let mut camera = Camera::builder().image_width(64).samples_per_pixel(4).build();
+//     let lights = HittableList::new();
+//     let image = camera.render_to_buffer(&world, &lights);
+
+pub mod aabb;
+pub mod bvh;
+pub mod camera;
+pub mod error;
+pub mod hittable;
+pub mod image;
+pub mod instance;
+pub mod interval;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod obj;
+pub mod output;
+pub mod perlin;
+pub mod principled;
+pub mod quad;
+pub mod ray;
+pub mod scene;
+pub mod sphere;
+pub mod stl;
+pub mod texture;
+pub mod tiles;
+pub mod trace;
+pub mod triangle;
+pub mod utils;
+pub mod volume;
+
+pub use bvh::{BvhNode, SplitStrategy};
+pub use camera::{Adaptive, Background, Camera, CameraBuilder, LightSampling, Progressive};
+pub use error::{Error, Result};
+pub use hittable::{HitRecord, Hittable, HittableList};
+pub use image::{Framebuffer, Image};
+pub use instance::{RotateY, Transform, Translate};
+pub use material::{BsdfSample, Material};
+pub use medium::ConstantMedium;
+pub use mesh::{MeshData, TriangleMesh};
+pub use output::OutputFormat;
+pub use principled::Principled;
+pub use quad::{make_box, Quad};
+pub use ray::Ray;
+pub use scene::Scene;
+pub use sphere::Sphere;
+pub use texture::Texture;
+pub use tiles::{Schedule, TileOrder};
+pub use triangle::Triangle;
+pub use volume::{GridVolume, VoxelGrid};
@@ -1,111 +1,196 @@
-mod aabb;
-mod camera;
-mod hittable;
-mod interval;
-mod material;
-mod ray;
-mod sphere;
-mod utils;
-
-use glam::DVec3;
-
-use crate::sphere::Sphere;
-use camera::Camera;
-use hittable::HittableList;
-use material::Material;
-use rand::Rng;
-use ray::Ray;
+mod cli;
+
+use clap::Parser;
+use cli::{Cli, Command, InfoArgs, RenderArgs, SceneArgs};
+use rayrusting::camera::Progressive;
+use rayrusting::scene::{self, Scene};
+use rayrusting::{BvhNode, Hittable, OutputFormat};
+use std::sync::atomic::Ordering;
 
 fn main() {
-    // Set up rng for later
-    let mut rng = rand::thread_rng();
-
-    // Create a scene to add objects to
-    let mut world = HittableList::default();
-
-    // Ground
-    world.add(Box::new(Sphere::new_stationary(
-        DVec3::new(0.0, -1000.0, -1.0),
-        1000.0,
-        Material::Lambertian {
-            albedo: DVec3::new(0.5, 0.5, 0.5),
-        },
-    )));
-
-    // Dielectric Ball
-    world.add(Box::new(Sphere::new_stationary(
-        DVec3::new(0.0, 1.0, 0.0),
-        1.0,
-        Material::Dielectric {
-            refraction_index: 1.5,
-        },
-    )));
-
-    // Lambertian Ball
-    world.add(Box::new(Sphere::new_stationary(
-        DVec3::new(-4.0, 1.0, 0.0),
-        1.0,
-        Material::Lambertian {
-            albedo: DVec3::new(0.4, 0.2, 0.1),
-        },
-    )));
-
-    // Metal Ball
-    world.add(Box::new(Sphere::new_stationary(
-        DVec3::new(4.0, 1.0, 0.0),
-        1.0,
-        Material::Metal {
-            albedo: DVec3::new(0.7, 0.6, 0.5),
-            fuzz: 0.0,
-        },
-    )));
-
-    for _ in 0..500 {
-        let spread = 6.0;
-        let vel = 1.0 / 3.0;
-
-        let center = DVec3::new(
-            (rng.gen::<f64>() - 0.5) * 2.0 * spread,
-            0.2,
-            (rng.gen::<f64>() - 0.5) * 2.0 * spread,
-        );
+    let cli = Cli::parse();
+
+    env_logger::Builder::new()
+        .filter_level(cli.log_level())
+        .parse_default_env()
+        .init();
 
-        let speed = DVec3::new(0.0, rng.gen::<f64>() * vel, 0.0);
-        let mat = match rng.gen::<f64>() {
-            x if x < 0.33 => Material::Lambertian {
-                albedo: DVec3::new(rng.gen(), rng.gen(), rng.gen()),
-            },
-            x if x < 0.66 => Material::Metal {
-                albedo: DVec3::new(rng.gen(), rng.gen(), rng.gen()),
-                fuzz: rng.gen::<f64>() / 2.0,
-            },
-            _ => Material::Dielectric {
-                refraction_index: 1.0 / (rng.gen::<f64>() + 0.5),
-            },
-        };
-
-        // Mini balls
-        world.add(Box::new(Sphere::new_moving(
-            Ray::with_direction(center, speed),
-            0.15,
-            mat,
-        )));
+    match cli.command {
+        Some(Command::Render(args)) => render(args),
+        Some(Command::Info(args)) => info(args),
+        None => render(RenderArgs::default()),
     }
+}
 
-    let mut cam: Camera = Camera::new();
+fn render(args: RenderArgs) {
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()
+            .expect("the thread pool is only configured once");
+    }
 
-    cam.aspect_ratio = 4.0 / 3.0;
-    cam.image_width = 320;
-    cam.samples_per_pixel = 50;
-    cam.max_depth = 100;
+    let Scene {
+        world,
+        lights,
+        mut camera,
+    } = load(&args.scene);
+    args.camera.apply(&mut camera);
+
+    if let Some((x, y)) = args.trace_pixel {
+        if x >= camera.image_width || y >= camera.image_height() {
+            eprintln!(
+                "pixel {x},{y} is outside the {}x{} image",
+                camera.image_width,
+                camera.image_height()
+            );
+            std::process::exit(1);
+        }
+        let world = BvhNode::from_list(world, args.bvh.into());
+        let trace = camera.trace_pixel(&world, &lights, x, y);
+        println!("{}", trace.to_json());
+        return;
+    }
 
-    cam.vfov = 20.0;
-    cam.look_from = DVec3::new(13.0, 2.0, 3.0);
-    cam.look_at = DVec3::new(0.0, 0.0, 0.0);
-    cam.look_up = DVec3::new(0.0, 1.0, 0.0);
+    // Catch a bad output path before spending time on the render
+    let Some(format) = camera.output_format() else {
+        eprintln!(
+            "{}: unknown image format; use a .png, .ppm, .hdr or .exr extension or pass --format",
+            camera.output.display()
+        );
+        std::process::exit(1);
+    };
+
+    let heatmap = args
+        .heatmap
+        .map(|path| match OutputFormat::from_path(&path) {
+            Some(format) => (path, format),
+            None => {
+                eprintln!(
+                    "{}: unknown image format; use a .png, .ppm, .hdr or .exr extension",
+                    path.display()
+                );
+                std::process::exit(1);
+            }
+        });
+
+    if world.is_empty() {
+        log::warn!("The scene has no objects, so only the sky will be visible");
+    }
+    log::info!("Rendering {} objects", world.len());
+
+    // Replace the flat list with a hierarchy so each ray only tests nearby objects
+    let world = BvhNode::from_list(world, args.bvh.into());
+
+    let limits = Progressive {
+        time_limit: args.time_limit,
+        checkpoint_interval: args.checkpoint,
+        ..Default::default()
+    };
+
+    // The first Ctrl-C finishes up with the samples taken so far, a second one gives up
+    let stop = limits.stop.clone();
+    let handler = ctrlc::set_handler(move || {
+        if stop.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        log::warn!("Stopping; press Ctrl-C again to quit without writing the image");
+    });
+    if let Err(e) = handler {
+        log::warn!("Ctrl-C will quit without writing the image: {e}");
+    }
 
-    cam.defocus_angle = 0.6;
-    cam.focus_dist = 10.0;
+    let output = camera.output.clone();
+    let image = camera.render_progressive(&world, &lights, &limits, |image| {
+        // A failed checkpoint doesn't stop the render; the final write will report it again
+        if let Err(e) = image.write(&output, format) {
+            log::warn!("{}: checkpoint not written: {e}", output.display());
+        } else {
+            log::info!("Wrote checkpoint to {}", output.display());
+        }
+    });
+
+    if let Err(e) = image.write(&output, format) {
+        eprintln!("{}: {e}", output.display());
+        std::process::exit(1);
+    }
+    log::info!("Wrote {}", output.display());
+    if let Some((path, format)) = heatmap {
+        if let Err(e) = image.sample_heatmap().write(&path, format) {
+            eprintln!("{}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+fn info(args: InfoArgs) {
+    let Scene {
+        world,
+        lights,
+        mut camera,
+    } = load(&args.scene);
+    args.camera.apply(&mut camera);
+
+    match &args.scene.scene {
+        Some(path) => println!("Scene:      {}", path.display()),
+        None => println!("Scene:      built-in"),
+    }
+    println!("Objects:    {}", world.len());
+    println!("Lights:     {}", lights.len());
+    match world.bounding_box().filter(|_| !world.is_empty()) {
+        Some(bbox) => println!(
+            "Bounds:     x [{}, {}]  y [{}, {}]  z [{}, {}]",
+            bbox.x.min, bbox.x.max, bbox.y.min, bbox.y.max, bbox.z.min, bbox.z.max
+        ),
+        None => println!("Bounds:     empty"),
+    }
+    println!(
+        "Image:      {}x{}, {} samples per pixel, max depth {}",
+        camera.image_width,
+        camera.image_height(),
+        camera.samples_per_pixel,
+        camera.max_depth
+    );
+    println!(
+        "Camera:     from {} looking at {}, vfov {}",
+        camera.look_from, camera.look_at, camera.vfov
+    );
+    println!(
+        "Focus:      distance {}, defocus angle {}",
+        camera.focus_dist, camera.defocus_angle
+    );
+    println!("Background: {:?}", camera.background);
+    println!("Sampling:   {:?}", camera.light_sampling);
+    println!("Schedule:   {:?}", camera.schedule);
+    if let Some(adaptive) = camera.adaptive {
+        println!(
+            "Adaptive:   threshold {}, at least {} samples per pixel",
+            adaptive.threshold, adaptive.min_samples
+        );
+    }
+    match camera.seed {
+        Some(seed) => println!("Seed:       {seed}"),
+        None => println!("Seed:       random"),
+    }
+    match camera.output_format() {
+        Some(format) => println!("Output:     {} ({format:?})", camera.output.display()),
+        None => println!("Output:     {} (unknown format)", camera.output.display()),
+    }
+}
 
-    cam.render(&world);
+// Loads the scene file given on the command line, or builds the default scene without one
+fn load(args: &SceneArgs) -> Scene {
+    match &args.scene {
+        Some(path) => {
+            let mut scene = scene::load_scene(path).unwrap_or_else(|e| {
+                eprintln!("{}: {e}", path.display());
+                std::process::exit(1);
+            });
+            if args.seed.is_some() {
+                scene.camera.seed = args.seed;
+            }
+            scene
+        }
+        None => scene::default_scene(args.seed),
+    }
 }
@@ -1,142 +1,213 @@
 use crate::hittable::HitRecord;
+use crate::principled::Principled;
 // Import necessary modules
 use crate::ray::Ray;
-use crate::utils::{near_zero, random_dvec3_unit};
+use crate::texture::Texture;
+use crate::utils::{self, random_dvec3_unit};
 use rand::Rng;
+use std::f64;
 
 use glam::DVec3;
 
 // Material enum defines different material types
 
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Clone)]
 pub enum Material {
     #[default]
     Default,
-    Lambertian {
-        albedo: DVec3,
+    // Scatters evenly in every direction, for the inside of fog and smoke
+    Isotropic {
+        albedo: Texture,
     },
-    Metal {
-        albedo: DVec3,
-        fuzz: f64,
+    // Scatters inside a volume with the Henyey-Greenstein phase function. `g` between -1 and 1
+    // leans scattering backwards (negative) or onwards (positive), and 0 is isotropic. Collisions
+    // also give off `emit`, for glowing gas such as fire.
+    HenyeyGreenstein {
+        albedo: Texture,
+        g: f64,
+        emit: Texture,
     },
-    Dielectric {
-        refraction_index: f64,
+    // Every kind of surface, from matte through metal to glass, from one set of parameters. See
+    // `Material::lambertian` and friends for the classic ones.
+    Principled(Box<Principled>),
+    // Emits light and scatters none
+    DiffuseLight {
+        emit: Texture,   // radiance leaving the surface
+        two_sided: bool, // whether the back face glows too
     },
 }
 
-impl Material {
-    pub fn scatter(&self, r_in: Ray, rec: &HitRecord, debug: bool) -> Option<(DVec3, Ray, bool)> {
-        use Material::*;
-
-        match self {
-            Default {} => Some((
-                DVec3::ZERO,
-                Ray::with_direction(DVec3::ONE, DVec3::ONE),
-                true,
-            )),
-            Lambertian { albedo } => {
-                let mut scatter_direction = rec.normal + random_dvec3_unit();
-
-                if near_zero(&scatter_direction) {
-                    scatter_direction = rec.normal;
-                }
+// One direction drawn by `Material::sample`.
+#[derive(Debug, Clone, Copy)]
+pub struct BsdfSample {
+    // Unit direction the light arrives from, pointing away from the surface
+    pub direction: DVec3,
+    // What light arriving along `direction` is scaled by: eval / pdf, or the attenuation of a
+    // delta lobe
+    pub weight: DVec3,
+    // Density per unit solid angle, or for a delta lobe the chance of having picked it
+    pub pdf: f64,
+    // Drawn from a lobe that only scatters in one exact direction, such as a mirror's. `eval` and
+    // `pdf` can't see these lobes, so lights can't be sampled into them either.
+    pub delta: bool,
+}
 
-                let scattered = Ray::with_time(rec.p, scatter_direction, r_in.time);
-                let attenuation = *albedo;
-                Some((attenuation, scattered, true))
-            }
-            Metal { albedo, fuzz } => {
-                let fuzz = if *fuzz > 1.0 { 1.0 } else { *fuzz };
-                let reflected = Self::reflect(r_in.direction, rec.normal).unwrap();
-                let reflected = reflected + (fuzz * random_dvec3_unit());
-
-                let scattered = Ray::with_time(rec.p, reflected, r_in.time);
-                let attenuation = *albedo;
-
-                // Returns false if a fuzzed ray ends up bouncing inside the surface
-                Some((
-                    attenuation,
-                    scattered,
-                    scattered.direction.dot(rec.normal) > 0.0,
-                ))
-            }
-            Dielectric { refraction_index } => {
-                let mut rng = rand::thread_rng();
+impl BsdfSample {
+    pub(crate) fn delta(direction: DVec3, weight: DVec3, pdf: f64) -> BsdfSample {
+        BsdfSample {
+            direction,
+            weight,
+            pdf,
+            delta: true,
+        }
+    }
+}
 
-                // White, no tinting
-                let attenuation = DVec3::ONE;
+impl Material {
+    // Matte, scattering light evenly in the books' Lambertian way.
+    pub fn lambertian(albedo: impl Into<Texture>) -> Material {
+        Principled::diffuse(albedo.into()).into()
+    }
 
-                let ri = if rec.front_face {
-                    refraction_index.recip()
-                } else {
-                    *refraction_index
-                };
+    // Metal tinted by `albedo`. As in the books a `fuzz` of 0 is a mirror and 1 is very blurry;
+    // it's used as the GGX width, which spreads reflections about as far.
+    pub fn metal(albedo: impl Into<Texture>, fuzz: f64) -> Material {
+        Principled::metal(albedo.into(), fuzz.clamp(0.0, 1.0).sqrt()).into()
+    }
 
-                let unit_direction = r_in.direction.normalize();
+    // Clear glass, or any other clear dielectric with the given index of refraction.
+    pub fn dielectric(refraction_index: f64) -> Material {
+        Principled::glass(refraction_index).into()
+    }
 
-                let cos_theta = (-unit_direction).dot(rec.normal).min(1.0);
-                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-                let cannot_refract = ri * sin_theta > 1.0;
+    // Scatters `r_in` at the hit, returning the attenuation and the scattered ray, or None if the
+    // light is absorbed.
+    pub fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(DVec3, Ray)> {
+        let sample = self.sample(-r_in.direction.normalize(), rec)?;
+        let scattered = Ray::with_time(rec.p, sample.direction, r_in.time);
+        Some((sample.weight, scattered))
+    }
 
-                let direction =
-                    if cannot_refract || Self::reflectance(cos_theta, ri) > rng.gen::<f64>() {
-                        Self::reflect(unit_direction, rec.normal).unwrap()
-                    } else {
-                        Self::refract(unit_direction, rec.normal, ri).unwrap()
-                    };
+    // Picks a direction for light to arrive from, given the unit direction `wo` it leaves along.
+    // Both point away from the surface. None if the light is absorbed.
+    pub fn sample(&self, wo: DVec3, rec: &HitRecord) -> Option<BsdfSample> {
+        use Material::*;
 
-                let scattered = Ray::with_time(rec.p, direction, r_in.time);
-                if debug {
-                    println!("material::scatter::scattered: {:?}", scattered);
-                    println!("material::scatter::direction: {:?}", direction);
-                }
-                Some((attenuation, scattered, true))
+        let smooth = |direction: DVec3| {
+            let pdf = self.pdf(wo, direction, rec);
+            if pdf.is_nan() || pdf <= 0.0 {
+                return None;
             }
+            Some(BsdfSample {
+                direction,
+                weight: self.eval(wo, direction, rec) / pdf,
+                pdf,
+                delta: false,
+            })
+        };
+
+        match self {
+            Default | DiffuseLight { .. } => None,
+            Isotropic { .. } => smooth(random_dvec3_unit()),
+            HenyeyGreenstein { g, .. } => smooth(sample_henyey_greenstein(-wo, *g)),
+            Principled(bsdf) => bsdf.sample(wo, rec),
         }
     }
 
-    fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
-        // Use Schlick's approximation for reflectance.
-        let r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
-        let r0 = r0 * r0;
-        r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
+    // Whether every way the material scatters is a delta lobe, as with mirrors and glass, so that
+    // `eval` is zero everywhere and there's no point aiming shadow rays at lights from it.
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Principled(bsdf) => bsdf.is_specular(),
+            _ => false,
+        }
     }
 
-    pub fn reflect(v: DVec3, n: DVec3) -> Option<DVec3> {
-        Some(v - 2.0 * v.dot(n) * n)
+    // The kind of material, for diagnostics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Material::Default => "default",
+            Material::Isotropic { .. } => "isotropic",
+            Material::HenyeyGreenstein { .. } => "henyey_greenstein",
+            Material::Principled(_) => "principled",
+            Material::DiffuseLight { .. } => "diffuse_light",
+        }
     }
 
-    pub fn refract(v: DVec3, n: DVec3, ni_over_nt: f64) -> Option<DVec3> {
-        let cos_theta = (-1.0 * v).dot(n).min(1.0);
-        let r_out_perp = ni_over_nt * (v + cos_theta * n);
-        let r_out_parallel = (-1.0 * (1.0 - r_out_perp.length_squared()).abs().sqrt()) * n;
-        Some(r_out_perp + r_out_parallel)
+    // Probability density, per unit solid angle, that `sample` picks the unit direction `wi`
+    // given `wo`. Delta lobes aren't included.
+    pub fn pdf(&self, wo: DVec3, wi: DVec3, rec: &HitRecord) -> f64 {
+        match self {
+            // Uniform over the sphere
+            Material::Isotropic { .. } => 1.0 / (4.0 * f64::consts::PI),
+            Material::HenyeyGreenstein { g, .. } => henyey_greenstein(-wo.dot(wi), *g),
+            Material::Principled(bsdf) => bsdf.pdf(wo, wi, rec),
+            _ => 0.0,
+        }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::material;
 
-    use super::*;
-
-    #[test]
-    fn refract_test() {
-        let r_in = DVec3::new(1.0, -1.0, 0.0).normalize();
-        let normal = DVec3::new(0.0, 1.0, 0.0);
+    // The fraction of light arriving along the unit direction `wi` that leaves along `wo`,
+    // including the cosine term. Delta lobes aren't included.
+    pub fn eval(&self, wo: DVec3, wi: DVec3, rec: &HitRecord) -> DVec3 {
+        match self {
+            // These all scatter in proportion to where they sample.
+            Material::Isotropic { albedo } | Material::HenyeyGreenstein { albedo, .. } => {
+                albedo.value(rec.u, rec.v, rec.p) * self.pdf(wo, wi, rec)
+            }
+            Material::Principled(bsdf) => bsdf.eval(wo, wi, rec),
+            _ => DVec3::ZERO,
+        }
+    }
 
-        let refract = Material::refract(r_in, normal, 1.0).unwrap();
+    // Light given off at the hit point, on top of anything scattered.
+    pub fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> DVec3 {
+        match self {
+            Material::DiffuseLight { emit, two_sided } if rec.front_face || *two_sided => {
+                emit.value(rec.u, rec.v, rec.p)
+            }
+            Material::HenyeyGreenstein { emit, .. } => emit.value(rec.u, rec.v, rec.p),
+            _ => DVec3::ZERO,
+        }
+    }
+}
 
-        assert!((r_in - refract).length().abs() < f64::EPSILON);
+impl From<Principled> for Material {
+    fn from(bsdf: Principled) -> Material {
+        Material::Principled(Box::new(bsdf))
+    }
+}
 
-        let r_in = DVec3::new(1.0, -1.0, 0.0).normalize();
-        let normal = DVec3::new(0.0, 1.0, 0.0);
+// Density, per unit solid angle, of the Henyey-Greenstein phase function turning a ray by an
+// angle whose cosine is `cos_theta`.
+fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * f64::consts::PI * denom * denom.sqrt())
+}
 
-        let refract = Material::refract(r_in, normal, 1.0 / 1.5).unwrap();
-        let expected = DVec3::new((2.0_f64.sqrt()) / 3.0, -(7.0_f64.sqrt()) / 3.0, 0.0).normalize();
+// A direction scattered from `direction` with the Henyey-Greenstein density for `g`.
+fn sample_henyey_greenstein(direction: DVec3, g: f64) -> DVec3 {
+    let mut rng = utils::rng();
+    let r1 = rng.gen::<f64>();
+    let r2 = rng.gen::<f64>();
+
+    // Inverting the cumulative distribution breaks down as g nears 0, where it's uniform anyway.
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * r1
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * r1);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * f64::consts::PI * r2;
+
+    let w = direction.normalize();
+    let (u, v) = w.any_orthonormal_pair();
+    u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * cos_theta
+}
 
-        assert!((expected - refract).length().abs() < f64::EPSILON);
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
     #[test]
     fn scatter_metallic_no_fuzz_test() {
@@ -145,10 +216,8 @@ mod tests {
             normal: DVec3::Y,
             t: 1.0,
             front_face: true,
-            mat: Material::Metal {
-                albedo: (DVec3::new(0.8, 0.1, 0.0)),
-                fuzz: 0.0,
-            },
+            mat: &Material::metal(DVec3::new(0.8, 0.1, 0.0), 0.0),
+            ..Default::default()
         };
 
         let r = Ray::with_time(
@@ -157,7 +226,7 @@ mod tests {
             0.0,
         );
 
-        let (_attenuation, scattered, _keeps_bouncing) = rec.mat.scatter(r, &rec, false).unwrap();
+        let (_attenuation, scattered) = rec.mat.scatter(r, &rec).unwrap();
 
         let reflected = DVec3::new(2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0, 0.0);
 
@@ -166,14 +235,16 @@ mod tests {
 
     #[test]
     fn scatter_dielectric_ref_1_5_entry_test() {
+        // Pin the random reflect-or-refract choice to a stream that refracts
+        utils::reseed(1, 0, 0);
+
         let rec = HitRecord {
             p: DVec3::ZERO,
             normal: DVec3::Y,
             t: 1.0,
             front_face: true,
-            mat: Material::Dielectric {
-                refraction_index: (1.5),
-            },
+            mat: &Material::dielectric(1.5),
+            ..Default::default()
         };
 
         let r = Ray::with_time(
@@ -182,7 +253,7 @@ mod tests {
             0.0,
         );
 
-        let (_attenuation, scattered, _keeps_bouncing) = rec.mat.scatter(r, &rec, false).unwrap();
+        let (_attenuation, scattered) = rec.mat.scatter(r, &rec).unwrap();
 
         let _reflected = DVec3::new(2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0, 0.0);
 
@@ -193,14 +264,16 @@ mod tests {
 
     #[test]
     fn scatter_dielectric_ref_1_5_exit_test() {
+        // Pin the random reflect-or-refract choice to a stream that refracts
+        utils::reseed(1, 0, 0);
+
         let rec = HitRecord {
             p: DVec3::ZERO,
             normal: DVec3::Y,
             t: 1.0,
             front_face: false,
-            mat: Material::Dielectric {
-                refraction_index: (1.5),
-            },
+            mat: &Material::dielectric(1.5),
+            ..Default::default()
         };
 
         let r = Ray::with_time(
@@ -209,7 +282,7 @@ mod tests {
             0.0,
         );
 
-        let (_attenuation, scattered, _keeps_bouncing) = rec.mat.scatter(r, &rec, false).unwrap();
+        let (_attenuation, scattered) = rec.mat.scatter(r, &rec).unwrap();
 
         let _reflected = DVec3::new(2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0, 0.0);
 
@@ -220,14 +293,16 @@ mod tests {
 
     #[test]
     fn scatter_dielectric_ref_1_0_dead_on_test() {
+        // Pin the random reflect-or-refract choice to a stream that refracts
+        utils::reseed(1, 0, 0);
+
         let rec = HitRecord {
             p: DVec3::ZERO,
             normal: DVec3::Y,
             t: 1.0,
             front_face: false,
-            mat: Material::Dielectric {
-                refraction_index: (1.5),
-            },
+            mat: &Material::dielectric(1.5),
+            ..Default::default()
         };
 
         let ray = Ray::with_time(
@@ -236,10 +311,317 @@ mod tests {
             0.0,
         );
 
-        let (_attenuation, scattered, _keeps_bouncing) = rec.mat.scatter(ray, &rec, false).unwrap();
+        let (_attenuation, scattered) = rec.mat.scatter(ray, &rec).unwrap();
 
         let expected = DVec3::new(0.0, -1.0, 0.0).normalize();
 
         assert!((scattered.direction - expected).length().abs() < f64::EPSILON);
     }
+
+    #[test]
+    fn diffuse_light_emits_test() {
+        let mat = Material::DiffuseLight {
+            emit: DVec3::new(4.0, 4.0, 4.0).into(),
+            two_sided: false,
+        };
+        let mut rec = HitRecord {
+            normal: DVec3::Y,
+            front_face: true,
+            mat: &mat,
+            ..Default::default()
+        };
+        let r = Ray::with_direction(DVec3::Y, -DVec3::Y);
+
+        assert!(mat.scatter(r, &rec).is_none());
+        assert_eq!(mat.emitted(&r, &rec), DVec3::splat(4.0));
+
+        // Only two sided lights glow from behind.
+        rec.front_face = false;
+        assert_eq!(mat.emitted(&r, &rec), DVec3::ZERO);
+        let two_sided = Material::DiffuseLight {
+            emit: DVec3::splat(4.0).into(),
+            two_sided: true,
+        };
+        assert_eq!(two_sided.emitted(&r, &rec), DVec3::splat(4.0));
+
+        // Other materials don't emit.
+        let lambertian = Material::lambertian(DVec3::ONE);
+        assert_eq!(lambertian.emitted(&r, &rec), DVec3::ZERO);
+    }
+
+    #[test]
+    fn lambertian_eval_matches_sample_test() {
+        let albedo = DVec3::new(0.8, 0.4, 0.2);
+        let mat = Material::lambertian(albedo);
+        let rec = HitRecord {
+            normal: DVec3::Y,
+            front_face: true,
+            mat: &mat,
+            ..Default::default()
+        };
+        let wo = DVec3::ONE.normalize();
+
+        assert!(!mat.is_specular());
+        let straight_up = mat.pdf(wo, DVec3::Y, &rec);
+        assert!((straight_up - 1.0 / f64::consts::PI).abs() < 1e-12);
+        assert_eq!(mat.pdf(wo, -DVec3::Y, &rec), 0.0);
+
+        // Every sampled direction has a density, and eval / pdf is the albedo.
+        for _ in 0..100 {
+            let sample = mat.sample(wo, &rec).unwrap();
+            assert!(!sample.delta);
+            let pdf = mat.pdf(wo, sample.direction, &rec);
+            assert!((pdf - sample.pdf).abs() < 1e-12);
+            let ratio = mat.eval(wo, sample.direction, &rec) / pdf;
+            assert!(ratio.abs_diff_eq(sample.weight, 1e-12));
+            assert!(ratio.abs_diff_eq(albedo, 1e-12));
+        }
+
+        let glass = Material::dielectric(1.5);
+        assert!(glass.is_specular());
+        assert_eq!(glass.pdf(wo, DVec3::Y, &rec), 0.0);
+        assert!(glass.sample(wo, &rec).unwrap().delta);
+    }
+
+    #[test]
+    fn isotropic_scatters_everywhere_test() {
+        let albedo = DVec3::new(0.8, 0.4, 0.2);
+        let mat = Material::Isotropic {
+            albedo: albedo.into(),
+        };
+        let rec = HitRecord {
+            normal: DVec3::X,
+            front_face: true,
+            mat: &mat,
+            ..Default::default()
+        };
+        let wo = DVec3::Z;
+
+        assert!(!mat.is_specular());
+        // Scattered directions cover the whole sphere, backwards included.
+        let mut mean = DVec3::ZERO;
+        for _ in 0..10_000 {
+            let sample = mat.sample(wo, &rec).unwrap();
+            assert!(sample.weight.abs_diff_eq(albedo, 1e-12));
+            mean += sample.direction / 10_000.0;
+
+            let pdf = mat.pdf(wo, sample.direction, &rec);
+            assert!((pdf - 1.0 / (4.0 * f64::consts::PI)).abs() < 1e-12);
+            let ratio = mat.eval(wo, sample.direction, &rec) / pdf;
+            assert!(ratio.abs_diff_eq(sample.weight, 1e-12));
+        }
+        assert!(mean.length() < 0.05, "{mean}");
+    }
+
+    #[test]
+    fn henyey_greenstein_test() {
+        let r = Ray::with_direction(DVec3::ZERO, DVec3::new(0.0, 0.0, -2.0));
+        let wo = DVec3::Z;
+        for g in [-0.7, 0.0, 0.3, 0.9] {
+            let mat = Material::HenyeyGreenstein {
+                albedo: DVec3::ONE.into(),
+                g,
+                emit: DVec3::ZERO.into(),
+            };
+            let rec = HitRecord {
+                mat: &mat,
+                ..Default::default()
+            };
+
+            // The mean cosine of the scattering angle is g.
+            let samples = 50_000;
+            let mut mean_cos = 0.0;
+            for _ in 0..samples {
+                let (_, scattered) = mat.scatter(r, &rec).unwrap();
+                assert!((scattered.direction.length() - 1.0).abs() < 1e-9);
+                mean_cos += scattered.direction.dot(-DVec3::Z) / samples as f64;
+            }
+            assert!((mean_cos - g).abs() < 0.01, "g = {g}: {mean_cos}");
+
+            // The density integrates to one over the sphere of directions. It only depends on
+            // the cosine to the forward direction, so that's all that needs integrating over.
+            let steps = 10_000;
+            let integral: f64 = (0..steps)
+                .map(|k| {
+                    let cos = -1.0 + 2.0 * (k as f64 + 0.5) / steps as f64;
+                    let wi = DVec3::new((1.0 - cos * cos).sqrt(), 0.0, -cos);
+                    2.0 * f64::consts::PI * mat.pdf(wo, wi, &rec) * 2.0 / steps as f64
+                })
+                .sum();
+            assert!((integral - 1.0).abs() < 1e-3, "g = {g}: {integral}");
+        }
+    }
+
+    #[test]
+    fn delta_lobes_test() {
+        let rec = HitRecord {
+            normal: DVec3::Y,
+            front_face: true,
+            ..Default::default()
+        };
+        let wo = DVec3::new(1.0, 1.0, 0.0).normalize();
+
+        // A sharp mirror reflects every time, and nothing else sees it.
+        let mirror = Material::metal(DVec3::splat(0.5), 0.0);
+        assert!(mirror.is_specular());
+        let sample = mirror.sample(wo, &rec).unwrap();
+        assert!(sample.delta);
+        assert_eq!(sample.pdf, 1.0);
+        assert!((sample.direction - DVec3::new(-1.0, 1.0, 0.0).normalize()).length() < 1e-12);
+        assert_eq!(mirror.pdf(wo, sample.direction, &rec), 0.0);
+        assert_eq!(mirror.eval(wo, sample.direction, &rec), DVec3::ZERO);
+
+        // Glass picks reflection as often as its pdf says: a chi-square test with two cells.
+        let glass = Material::dielectric(1.5);
+        utils::reseed(7, 0, 0);
+        let samples = 20_000;
+        let mut reflected = 0.0;
+        let mut reflectance = 0.0;
+        for _ in 0..samples {
+            let sample = glass.sample(wo, &rec).unwrap();
+            assert!(sample.delta);
+            assert_eq!(sample.weight, DVec3::ONE);
+            if sample.direction.y > 0.0 {
+                reflected += 1.0;
+                reflectance = sample.pdf;
+            }
+        }
+        let expected = [
+            reflectance * samples as f64,
+            (1.0 - reflectance) * samples as f64,
+        ];
+        let observed = [reflected, samples as f64 - reflected];
+        let chi2: f64 = (0..2)
+            .map(|i| (observed[i] - expected[i]).powi(2) / expected[i])
+            .sum();
+        // The 99.99th percentile with one degree of freedom
+        assert!(chi2 < 15.1, "{chi2}");
+    }
+
+    // Pearson's chi-square test of `sample` drawing directions as `pdf` says it does. Directions
+    // are counted in cells of equal solid angle, evenly spaced in the cosine to the normal and the
+    // angle around it, with one more cell for samples absorbed. Returns how many standard
+    // deviations the statistic is above its mean, by the Wilson-Hilferty approximation.
+    fn chi_square_z(mat: &Material, wo: DVec3, rec: &HitRecord) -> f64 {
+        const COS_CELLS: usize = 10;
+        const PHI_CELLS: usize = 20;
+        // Points per cell side when integrating the pdf over it
+        const STEPS: usize = 8;
+        let samples = 50_000;
+        // A fixed stream, so the test can't fail by chance
+        utils::reseed(7, 0, 0);
+
+        let (t, b) = rec.normal.any_orthonormal_pair();
+        let d_cos = 2.0 / COS_CELLS as f64;
+        let d_phi = 2.0 * f64::consts::PI / PHI_CELLS as f64;
+        let direction = |cos: f64, phi: f64| {
+            let sin = (1.0 - cos * cos).max(0.0).sqrt();
+            sin * phi.cos() * t + sin * phi.sin() * b + cos * rec.normal
+        };
+
+        let mut observed = vec![0.0; COS_CELLS * PHI_CELLS + 1];
+        for _ in 0..samples {
+            let cell = match mat.sample(wo, rec) {
+                Some(sample) => {
+                    let w = sample.direction;
+                    let cos = w.dot(rec.normal).clamp(-1.0, 1.0);
+                    let phi = w.dot(b).atan2(w.dot(t)).rem_euclid(2.0 * f64::consts::PI);
+                    let i = (((cos + 1.0) / d_cos) as usize).min(COS_CELLS - 1);
+                    let j = ((phi / d_phi) as usize).min(PHI_CELLS - 1);
+                    i * PHI_CELLS + j
+                }
+                None => COS_CELLS * PHI_CELLS,
+            };
+            observed[cell] += 1.0;
+        }
+
+        let mut expected = vec![0.0; COS_CELLS * PHI_CELLS + 1];
+        for i in 0..COS_CELLS {
+            for j in 0..PHI_CELLS {
+                let mut integral = 0.0;
+                for k in 0..STEPS {
+                    for l in 0..STEPS {
+                        let cos = -1.0 + d_cos * (i as f64 + (k as f64 + 0.5) / STEPS as f64);
+                        let phi = d_phi * (j as f64 + (l as f64 + 0.5) / STEPS as f64);
+                        integral += mat.pdf(wo, direction(cos, phi), rec);
+                    }
+                }
+                let area = d_cos * d_phi / (STEPS * STEPS) as f64;
+                expected[i * PHI_CELLS + j] = samples as f64 * integral * area;
+            }
+        }
+        let scattered: f64 = expected.iter().sum();
+        expected[COS_CELLS * PHI_CELLS] = (samples as f64 - scattered).max(0.0);
+
+        // Cells expecting too few samples for the test to hold are lumped together.
+        let (mut chi2, mut cells) = (0.0, 0);
+        let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+        for (o, e) in observed.iter().zip(&expected) {
+            if *e < 5.0 {
+                pooled_observed += o;
+                pooled_expected += e;
+            } else {
+                chi2 += (o - e) * (o - e) / e;
+                cells += 1;
+            }
+        }
+        if pooled_expected > 0.0 {
+            let (o, e) = (pooled_observed, pooled_expected);
+            chi2 += (o - e) * (o - e) / e.max(5.0);
+            cells += 1;
+        }
+
+        let dof = (cells - 1) as f64;
+        let a = 2.0 / (9.0 * dof);
+        ((chi2 / dof).powf(1.0 / 3.0) - (1.0 - a)) / a.sqrt()
+    }
+
+    #[test]
+    fn chi_square_test() {
+        let wo = DVec3::new(0.4, 0.8, 0.3).normalize();
+        let materials = [
+            Material::lambertian(DVec3::splat(0.5)),
+            Material::metal(DVec3::splat(0.5), 0.4),
+            Material::Isotropic {
+                albedo: DVec3::splat(0.5).into(),
+            },
+            Material::HenyeyGreenstein {
+                albedo: DVec3::splat(0.5).into(),
+                g: 0.5,
+                emit: DVec3::ZERO.into(),
+            },
+            Material::HenyeyGreenstein {
+                albedo: DVec3::splat(0.5).into(),
+                g: -0.3,
+                emit: DVec3::ZERO.into(),
+            },
+            Principled::default().into(),
+            Principled {
+                roughness: 0.5,
+                ..Principled::glass(1.5)
+            }
+            .into(),
+            Principled {
+                clearcoat: 1.0,
+                clearcoat_roughness: 0.3,
+                sheen: 1.0,
+                ..Principled::default()
+            }
+            .into(),
+        ];
+
+        for front_face in [true, false] {
+            for mat in &materials {
+                let rec = HitRecord {
+                    normal: DVec3::Y,
+                    front_face,
+                    mat,
+                    ..Default::default()
+                };
+                assert!(!mat.is_specular(), "{}", mat.name());
+                let z = chi_square_z(mat, wo, &rec);
+                assert!(z < 4.0, "{} (front face {front_face}): z = {z}", mat.name());
+            }
+        }
+    }
 }
@@ -0,0 +1,154 @@
+use crate::aabb::AABB;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::{self, Interval};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::utils;
+use glam::DVec3;
+use rand::Rng;
+
+// Fog or smoke of even density filling a boundary object. A ray passing through is scattered at
+// a random distance in, drawn so the chance of getting through falls off exponentially with the
+// distance travelled inside. The boundary must be convex: a ray can only enter and leave it once.
+pub struct ConstantMedium<T: Hittable = Box<dyn Hittable>> {
+    boundary: T,
+    neg_inv_density: f64,
+    phase_function: Material,
+}
+
+impl<T: Hittable> ConstantMedium<T> {
+    // `density` is the chance of scattering per unit distance. The boundary's own material is
+    // ignored.
+    pub fn new(boundary: T, density: f64, phase_function: Material) -> ConstantMedium<T> {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+
+    // A medium scattering the same amount in every direction.
+    pub fn isotropic(boundary: T, density: f64, albedo: Texture) -> ConstantMedium<T> {
+        ConstantMedium::new(boundary, density, Material::Isotropic { albedo })
+    }
+}
+
+impl<T: Hittable> Hittable for ConstantMedium<T> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        // Find where the whole line crosses the boundary, so that rays starting inside, whose
+        // entry is behind them, still see how much medium is ahead.
+        let entry = self.boundary.hit(r, interval::UNIVERSE)?;
+        let exit = self
+            .boundary
+            .hit(r, Interval::new(entry.t + 0.0001, f64::INFINITY))?;
+
+        let t_enter = entry.t.max(ray_t.min).max(0.0);
+        let t_exit = exit.t.min(ray_t.max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = r.direction.length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * utils::rng().gen::<f64>().ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        // A point in a volume has no surface, so the normal and face are arbitrary.
+        Some(HitRecord {
+            p: r.at(t),
+            t,
+            normal: DVec3::X,
+            geometric_normal: DVec3::X,
+            front_face: true,
+            mat: &self.phase_function,
+            ..Default::default()
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+
+    fn fog(density: f64) -> ConstantMedium<Sphere> {
+        let boundary = Sphere::new_stationary(DVec3::new(0.0, 0.0, -3.0), 1.0, Material::default());
+        ConstantMedium::isotropic(boundary, density, DVec3::ONE.into())
+    }
+
+    fn forward() -> Interval {
+        Interval::new(0.001, f64::INFINITY)
+    }
+
+    #[test]
+    fn transmittance_test() {
+        // The diameter is 2 long, so half a unit of density lets through e^-1 of the rays.
+        let medium = fog(0.5);
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+        // Count over a fixed stream, so the test can't fail by chance.
+        utils::reseed(1, 0, 0);
+        let rays = 20_000;
+        let mut through = 0;
+        for _ in 0..rays {
+            match medium.hit(&r, forward()) {
+                Some(rec) => {
+                    assert!(rec.t > 2.0 && rec.t < 4.0, "{}", rec.t);
+                    assert!(matches!(rec.mat, Material::Isotropic { .. }));
+                }
+                None => through += 1,
+            }
+        }
+        let transmittance = through as f64 / rays as f64;
+        assert!(
+            (transmittance - (-1.0f64).exp()).abs() < 0.02,
+            "{transmittance}"
+        );
+
+        // Rays that miss the boundary never scatter.
+        let r = Ray::with_direction(DVec3::new(2.0, 0.0, 0.0), -DVec3::Z);
+        assert!(medium.hit(&r, forward()).is_none());
+    }
+
+    #[test]
+    fn starts_inside_test() {
+        let medium = fog(1000.0);
+        // From the middle of the fog the scattering point is right ahead, not back at the entry.
+        let r = Ray::with_direction(DVec3::new(0.0, 0.0, -3.0), DVec3::X);
+        let rec = medium.hit(&r, forward()).unwrap();
+        assert!(rec.t > 0.0 && rec.t < 0.05, "{}", rec.t);
+    }
+
+    #[test]
+    fn respects_ray_t_test() {
+        let medium = fog(1000.0);
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+
+        // Something opaque in front of the fog
+        assert!(medium.hit(&r, Interval::new(0.001, 1.5)).is_none());
+
+        // The interval starts partway into the fog
+        let rec = medium.hit(&r, Interval::new(3.0, f64::INFINITY)).unwrap();
+        assert!(rec.t > 3.0 && rec.t < 3.05, "{}", rec.t);
+
+        // Thinner fog cut short by the interval only scatters inside it.
+        let medium = fog(0.5);
+        for _ in 0..1000 {
+            if let Some(rec) = medium.hit(&r, Interval::new(0.001, 2.5)) {
+                assert!(rec.t < 2.5);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::aabb::AABB;
+use crate::bvh::{BvhNode, SplitStrategy};
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::triangle;
+use glam::{DVec2, DVec3};
+use std::ops::Range;
+use std::sync::Arc;
+
+// One triangle of a mesh, as indices into the mesh's shared vertex attributes. Normals and
+// texture coordinates are indexed separately from positions, as they are in OBJ files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub texcoords: Option<[usize; 3]>,
+}
+
+// A named run of consecutive faces, such as an OBJ `g` group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeshGroup {
+    pub name: String,
+    pub faces: Range<usize>,
+}
+
+// Raw indexed triangle data. Every index in `faces` must be in range for its attribute.
+#[derive(Debug, Default, Clone)]
+pub struct MeshData {
+    pub positions: Vec<DVec3>,
+    pub normals: Vec<DVec3>,
+    pub texcoords: Vec<DVec2>,
+    pub faces: Vec<MeshFace>,
+    pub groups: Vec<MeshGroup>,
+}
+
+// Shared by the mesh and every one of its triangles.
+struct MeshShared {
+    data: MeshData,
+    mat: Material,
+}
+
+// A triangle that refers back into its mesh instead of holding copies of its vertices.
+struct MeshTriangle {
+    mesh: Arc<MeshShared>,
+    index: usize,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> [DVec3; 3] {
+        let positions = &self.mesh.data.positions;
+        self.mesh.data.faces[self.index]
+            .positions
+            .map(|i| positions[i])
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let data = &self.mesh.data;
+        let face = &data.faces[self.index];
+        let vertices = self.vertices();
+
+        let (t, bary) = triangle::intersect(r, vertices[0], vertices[1], vertices[2], ray_t)?;
+
+        let normals = face.normals.map(|n| n.map(|i| data.normals[i]));
+        let mut rec = triangle::surface_record(r, t, bary, vertices, normals, &self.mesh.mat);
+
+        if let Some([t0, t1, t2]) = face.texcoords {
+            let uv = bary.x * data.texcoords[t0]
+                + bary.y * data.texcoords[t1]
+                + bary.z * data.texcoords[t2];
+            rec.u = uv.x;
+            rec.v = uv.y;
+        }
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let [v0, v1, v2] = self.vertices();
+        Some(
+            AABB::from_boxes(&AABB::from_points(v0, v1), &AABB::from_points(v2, v2))
+                .pad_to_minimums(),
+        )
+    }
+}
+
+// A triangle mesh with a single material, whose triangles share one copy of the vertex data and
+// are organized into their own BVH.
+pub struct TriangleMesh {
+    mesh: Arc<MeshShared>,
+    bvh: BvhNode<MeshTriangle>,
+}
+
+impl TriangleMesh {
+    pub fn new(data: MeshData, mat: Material) -> TriangleMesh {
+        let mesh = Arc::new(MeshShared { data, mat });
+        let triangles = (0..mesh.data.faces.len())
+            .map(|index| MeshTriangle {
+                mesh: mesh.clone(),
+                index,
+            })
+            .collect();
+
+        TriangleMesh {
+            bvh: BvhNode::new(triangles, SplitStrategy::Sah),
+            mesh,
+        }
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.mesh.data
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.mesh.data.faces.len()
+    }
+
+    pub fn groups(&self) -> &[MeshGroup] {
+        &self.mesh.data.groups
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        self.bvh.hit(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bvh.bounding_box()
+    }
+}
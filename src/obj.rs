@@ -0,0 +1,392 @@
+// Wavefront OBJ parsing.
+//
+// Supports vertex positions (`v`), normals (`vn`), texture coordinates (`vt`), polygonal faces
+// (`f`) with any of the `v`, `v/vt`, `v//vn` and `v/vt/vn` corner forms including negative
+// (relative) indices, and groups (`g`). Polygons are triangulated as fans around their first
+// corner. Any other statements, such as materials and smoothing groups, are skipped.
+
+use crate::material::Material;
+use crate::mesh::{MeshData, MeshFace, MeshGroup, TriangleMesh};
+use glam::{DVec2, DVec3};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "failed to read OBJ: {e}"),
+            ObjError::Parse { line, message } => write!(f, "OBJ line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io(e) => Some(e),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ObjError {
+    fn from(e: std::io::Error) -> ObjError {
+        ObjError::Io(e)
+    }
+}
+
+// Loads an OBJ file from disk as a single mesh with one material.
+pub fn load_obj(path: impl AsRef<Path>, mat: Material) -> Result<TriangleMesh, ObjError> {
+    let file = File::open(path)?;
+    let data = parse_obj(BufReader::new(file))?;
+    Ok(TriangleMesh::new(data, mat))
+}
+
+pub fn parse_obj(reader: impl BufRead) -> Result<MeshData, ObjError> {
+    let mut parser = Parser::default();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        parser
+            .parse_line(&line)
+            .map_err(|message| ObjError::Parse {
+                line: i + 1,
+                message,
+            })?;
+    }
+
+    Ok(parser.finish())
+}
+
+#[derive(Default)]
+struct Parser {
+    data: MeshData,
+    // Name and first face of the group currently being read, if any
+    group: Option<(String, usize)>,
+}
+
+// A face corner's indices, already resolved to zero-based positions in the attribute lists.
+struct Corner {
+    position: usize,
+    texcoord: Option<usize>,
+    normal: Option<usize>,
+}
+
+impl Parser {
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        // Strip comments, then split into a keyword and its arguments.
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            return Ok(());
+        };
+        let args: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => {
+                // An optional fourth `w` component is allowed but unused.
+                let v = Self::parse_floats(keyword, &args, 3, 4)?;
+                self.data.positions.push(DVec3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let n = Self::parse_floats(keyword, &args, 3, 3)?;
+                self.data.normals.push(DVec3::new(n[0], n[1], n[2]));
+            }
+            "vt" => {
+                // Only `u` is required; `v` defaults to 0 and `w` is unused.
+                let t = Self::parse_floats(keyword, &args, 1, 3)?;
+                self.data
+                    .texcoords
+                    .push(DVec2::new(t[0], t.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => self.parse_face(&args)?,
+            "g" => {
+                self.close_group();
+                let name = if args.is_empty() {
+                    "default".to_string()
+                } else {
+                    args.join(" ")
+                };
+                self.group = Some((name, self.data.faces.len()));
+            }
+            // Objects, materials, smoothing groups, free-form curves and so on have nothing to
+            // contribute to a single-material triangle mesh.
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn parse_floats(
+        keyword: &str,
+        args: &[&str],
+        min: usize,
+        max: usize,
+    ) -> Result<Vec<f64>, String> {
+        if args.len() < min || args.len() > max {
+            let expected = if min == max {
+                format!("{min}")
+            } else {
+                format!("{min} to {max}")
+            };
+            return Err(format!(
+                "`{keyword}` expects {expected} numbers, found {}",
+                args.len()
+            ));
+        }
+
+        args.iter()
+            .map(|a| {
+                a.parse::<f64>()
+                    .map_err(|_| format!("`{keyword}` has an invalid number `{a}`"))
+            })
+            .collect()
+    }
+
+    fn parse_face(&mut self, args: &[&str]) -> Result<(), String> {
+        if args.len() < 3 {
+            return Err(format!(
+                "`f` needs at least 3 vertices, found {}",
+                args.len()
+            ));
+        }
+
+        let corners = args
+            .iter()
+            .map(|a| self.parse_corner(a))
+            .collect::<Result<Vec<Corner>, String>>()?;
+
+        // Only keep an attribute if every corner of the face has one.
+        let has_texcoords = corners.iter().all(|c| c.texcoord.is_some());
+        let has_normals = corners.iter().all(|c| c.normal.is_some());
+
+        // Fan triangulation around the first corner, preserving winding.
+        for i in 1..corners.len() - 1 {
+            let tri = [&corners[0], &corners[i], &corners[i + 1]];
+            self.data.faces.push(MeshFace {
+                positions: tri.map(|c| c.position),
+                texcoords: has_texcoords.then(|| tri.map(|c| c.texcoord.unwrap())),
+                normals: has_normals.then(|| tri.map(|c| c.normal.unwrap())),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn parse_corner(&self, corner: &str) -> Result<Corner, String> {
+        let mut parts = corner.split('/');
+
+        let position =
+            Self::resolve_index(parts.next(), self.data.positions.len(), "position", corner)?
+                .ok_or_else(|| format!("face corner `{corner}` is missing a position index"))?;
+        let texcoord = Self::resolve_index(
+            parts.next(),
+            self.data.texcoords.len(),
+            "texture coordinate",
+            corner,
+        )?;
+        let normal = Self::resolve_index(parts.next(), self.data.normals.len(), "normal", corner)?;
+
+        if parts.next().is_some() {
+            return Err(format!(
+                "face corner `{corner}` has too many `/` separators"
+            ));
+        }
+
+        Ok(Corner {
+            position,
+            texcoord,
+            normal,
+        })
+    }
+
+    // OBJ indices are 1-based, and negative values count back from the most recent element.
+    fn resolve_index(
+        index: Option<&str>,
+        count: usize,
+        what: &str,
+        corner: &str,
+    ) -> Result<Option<usize>, String> {
+        let Some(index) = index.filter(|i| !i.is_empty()) else {
+            return Ok(None);
+        };
+
+        let i: i64 = index
+            .parse()
+            .map_err(|_| format!("face corner `{corner}` has an invalid {what} index `{index}`"))?;
+
+        let resolved = match i {
+            i if i > 0 => i - 1,
+            i if i < 0 => count as i64 + i,
+            _ => {
+                return Err(format!(
+                    "face corner `{corner}` uses index 0, but OBJ indices start at 1"
+                ))
+            }
+        };
+
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(format!(
+                "face corner `{corner}` refers to {what} {i}, but only {count} have been defined"
+            ));
+        }
+
+        Ok(Some(resolved as usize))
+    }
+
+    fn close_group(&mut self) {
+        if let Some((name, start)) = self.group.take() {
+            let end = self.data.faces.len();
+            if end > start {
+                self.data.groups.push(MeshGroup {
+                    name,
+                    faces: start..end,
+                });
+            }
+        }
+    }
+
+    fn finish(mut self) -> MeshData {
+        self.close_group();
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    fn parse(src: &str) -> Result<MeshData, ObjError> {
+        parse_obj(src.as_bytes())
+    }
+
+    fn parse_error_line(src: &str) -> usize {
+        match parse(src) {
+            Err(ObjError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    const QUAD: &str = "
+# A unit quad in the xy plane
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+
+    #[test]
+    fn quad_fan_triangulation_test() {
+        let data = parse(QUAD).unwrap();
+
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.texcoords.len(), 4);
+        assert_eq!(data.normals.len(), 1);
+        assert_eq!(
+            data.faces,
+            vec![
+                MeshFace {
+                    positions: [0, 1, 2],
+                    texcoords: Some([0, 1, 2]),
+                    normals: Some([0, 0, 0]),
+                },
+                MeshFace {
+                    positions: [0, 2, 3],
+                    texcoords: Some([0, 2, 3]),
+                    normals: Some([0, 0, 0]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn corner_forms_and_negative_indices_test() {
+        let data = parse(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nvt 0.5 0.5\n\
+             f 1 2 3\nf -3//-1 -2//-1 -1//-1\nf 1/1 2/1 3/1\n",
+        )
+        .unwrap();
+
+        assert_eq!(data.faces.len(), 3);
+        assert_eq!(data.faces[0].normals, None);
+        assert_eq!(data.faces[0].texcoords, None);
+        assert_eq!(data.faces[1].positions, [0, 1, 2]);
+        assert_eq!(data.faces[1].normals, Some([0, 0, 0]));
+        assert_eq!(data.faces[2].texcoords, Some([0, 0, 0]));
+    }
+
+    #[test]
+    fn groups_test() {
+        let data = parse(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n\
+             f 1 2 3\ng left arm\nf 1 2 3\nf 2 4 3\ng empty\ng right\nf 1 2 4\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            data.groups,
+            vec![
+                MeshGroup {
+                    name: "left arm".to_string(),
+                    faces: 1..3,
+                },
+                MeshGroup {
+                    name: "right".to_string(),
+                    faces: 3..4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_lines_report_line_numbers_test() {
+        assert_eq!(parse_error_line("v 0 0 0\nv 1 0\n"), 2);
+        assert_eq!(parse_error_line("v 0 0 0\n\nv 1 zero 0\n"), 3);
+        assert_eq!(parse_error_line("v 0 0 0\nv 1 0 0\nf 1 2\n"), 3);
+        assert_eq!(parse_error_line("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"), 4);
+        assert_eq!(parse_error_line("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n"), 4);
+        assert_eq!(
+            parse_error_line("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1//1 2//1 3//1\n"),
+            4
+        );
+    }
+
+    #[test]
+    fn mesh_hit_test() {
+        let mesh = TriangleMesh::new(parse(QUAD).unwrap(), Material::lambertian(DVec3::ONE));
+        assert_eq!(mesh.triangle_count(), 2);
+
+        let r = Ray::with_direction(DVec3::new(0.25, 0.75, 1.0), -DVec3::Z);
+        let rec = mesh
+            .hit(&r, Interval::new(f64::EPSILON, f64::INFINITY))
+            .unwrap();
+
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.normal - DVec3::Z).length() < 1e-12);
+        // Texture coordinates are interpolated from the vertices.
+        assert!((rec.u - 0.25).abs() < 1e-12);
+        assert!((rec.v - 0.75).abs() < 1e-12);
+
+        let r = Ray::with_direction(DVec3::new(1.5, 0.5, 1.0), -DVec3::Z);
+        assert!(mesh
+            .hit(&r, Interval::new(f64::EPSILON, f64::INFINITY))
+            .is_none());
+    }
+}
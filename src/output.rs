@@ -0,0 +1,439 @@
+// Image encoders for the rendered frame.
+//
+// Every encoder takes linear, unclamped pixel colors in row-major order starting from the top
+// left. The low dynamic range formats apply gamma correction and quantization themselves, while
+// the high dynamic range ones store the linear values as they are.
+
+use crate::image::Image;
+use crate::interval::Interval;
+use glam::DVec3;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    // Plain text P3 PPM, one pixel per line
+    PpmAscii,
+    // Raw P6 PPM
+    PpmBinary,
+    // PNG with 8 bits per channel
+    Png8,
+    // PNG with 16 bits per channel
+    Png16,
+    // Radiance RGBE, with a shared 8 bit exponent per pixel
+    Hdr,
+    // OpenEXR with linear floating point channels
+    Exr(ExrOptions),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExrOptions {
+    pub precision: ExrPrecision,
+    pub compression: ExrCompression,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExrPrecision {
+    // 16 bit floats, which is plenty for color and halves the file size
+    #[default]
+    Half,
+    // 32 bit floats
+    Float,
+}
+
+// The lossless compression schemes OpenEXR offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExrCompression {
+    None,
+    Rle,
+    #[default]
+    Zip,
+    Piz,
+}
+
+impl OutputFormat {
+    // Picks a format from the file extension. PPM stays plain text P3, as it always has been;
+    // binary P6 has to be asked for. Returns None for extensions without an encoder.
+    pub fn from_path(path: &Path) -> Option<OutputFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(OutputFormat::PpmAscii),
+            "png" => Some(OutputFormat::Png8),
+            "hdr" => Some(OutputFormat::Hdr),
+            "exr" => Some(OutputFormat::Exr(ExrOptions::default())),
+            _ => None,
+        }
+    }
+}
+
+pub fn write_image(path: &Path, format: OutputFormat, image: &Image) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode(&mut writer, format, image)?;
+    writer.flush()
+}
+
+pub fn encode(writer: &mut impl Write, format: OutputFormat, image: &Image) -> std::io::Result<()> {
+    let (width, height, pixels) = (image.width, image.height, &image.pixels[..]);
+
+    match format {
+        OutputFormat::PpmAscii => {
+            write_header(writer, width, height)?;
+            for color in pixels {
+                write_color(writer, color)?;
+            }
+            Ok(())
+        }
+        OutputFormat::PpmBinary => {
+            writeln!(writer, "P6")?;
+            writeln!(writer, "{width} {height}")?;
+            writeln!(writer, "255")?;
+            let bytes: Vec<u8> = pixels.iter().flat_map(to_rgb8).collect();
+            writer.write_all(&bytes)
+        }
+        OutputFormat::Png8 => {
+            let bytes: Vec<u8> = pixels.iter().flat_map(to_rgb8).collect();
+            write_png(writer, width, height, png::BitDepth::Eight, &bytes)
+        }
+        OutputFormat::Png16 => {
+            // PNG stores 16 bit samples big-endian.
+            let bytes: Vec<u8> = pixels
+                .iter()
+                .flat_map(to_rgb16)
+                .flat_map(u16::to_be_bytes)
+                .collect();
+            write_png(writer, width, height, png::BitDepth::Sixteen, &bytes)
+        }
+        OutputFormat::Hdr => write_hdr(writer, width, height, pixels),
+        OutputFormat::Exr(options) => write_exr(writer, width, height, pixels, options),
+    }
+}
+
+// Writes a Radiance picture with flat (not run length encoded) scanlines, which every reader
+// accepts.
+fn write_hdr(
+    writer: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[DVec3],
+) -> std::io::Result<()> {
+    writeln!(writer, "#?RADIANCE")?;
+    writeln!(writer, "FORMAT=32-bit_rle_rgbe")?;
+    writeln!(writer)?;
+    // Rows run down the image and columns to the right, matching the pixel order.
+    writeln!(writer, "-Y {height} +X {width}")?;
+    let bytes: Vec<u8> = pixels.iter().flat_map(to_rgbe).collect();
+    writer.write_all(&bytes)
+}
+
+// Packs a color into three 8 bit mantissas sharing the exponent of the brightest channel.
+fn to_rgbe(color: &DVec3) -> [u8; 4] {
+    // RGBE can't store negative values. Taking the max also replaces NaN with zero. Anything
+    // brighter than the largest value the format holds, infinity included, is clamped to it.
+    let largest = 255.0 / 256.0 * 2f64.powi(127);
+    let color = color.max(DVec3::ZERO).min(DVec3::splat(largest));
+    let brightest = color.max_element();
+    if brightest < 1e-32 {
+        return [0; 4];
+    }
+
+    // brightest = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let exponent = brightest.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(exponent);
+    let quantize = |c: f64| (c * scale).min(255.0) as u8;
+    [
+        quantize(color.x),
+        quantize(color.y),
+        quantize(color.z),
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+fn write_exr(
+    writer: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[DVec3],
+    options: ExrOptions,
+) -> std::io::Result<()> {
+    use exr::prelude::{
+        f16, Blocks, Compression, Encoding, Image, LineOrder, SpecificChannels, Vec2, WritableImage,
+    };
+
+    let encoding = Encoding {
+        compression: match options.compression {
+            ExrCompression::None => Compression::Uncompressed,
+            ExrCompression::Rle => Compression::RLE,
+            ExrCompression::Zip => Compression::ZIP16,
+            ExrCompression::Piz => Compression::PIZ,
+        },
+        blocks: Blocks::ScanLines,
+        line_order: LineOrder::Increasing,
+    };
+    let pixel = |position: Vec2<usize>| pixels[position.y() * width + position.x()];
+
+    // The exr writer needs to seek back to fill in its offset tables, so encode in memory first.
+    let mut buffer = Cursor::new(Vec::new());
+    let result = match options.precision {
+        ExrPrecision::Half => Image::from_encoded_channels(
+            (width, height),
+            encoding,
+            SpecificChannels::rgb(|position| {
+                let c = pixel(position);
+                (f16::from_f64(c.x), f16::from_f64(c.y), f16::from_f64(c.z))
+            }),
+        )
+        .write()
+        .to_buffered(&mut buffer),
+        ExrPrecision::Float => Image::from_encoded_channels(
+            (width, height),
+            encoding,
+            SpecificChannels::rgb(|position| {
+                let c = pixel(position).as_vec3();
+                (c.x, c.y, c.z)
+            }),
+        )
+        .write()
+        .to_buffered(&mut buffer),
+    };
+    result.map_err(|e| match e {
+        exr::error::Error::Io(e) => e,
+        e => std::io::Error::other(e.to_string()),
+    })?;
+
+    writer.write_all(buffer.get_ref())
+}
+
+fn write_png(
+    writer: &mut impl Write,
+    width: usize,
+    height: usize,
+    depth: png::BitDepth,
+    data: &[u8],
+) -> std::io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(depth);
+    // The gamma of 2 used by `linear_to_gamma`
+    encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.0));
+
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(data)?;
+    png_writer.finish()?;
+    Ok(())
+}
+
+pub fn write_header(writer: &mut impl Write, width: usize, height: usize) -> std::io::Result<()> {
+    writeln!(writer, "P3")?;
+    writeln!(writer, "{width} {height}")?;
+    writeln!(writer, "255")
+}
+
+pub fn write_color(writer: &mut impl Write, color: &DVec3) -> std::io::Result<()> {
+    let [r, g, b] = to_rgb8(color);
+    writeln!(writer, "{r:>3} {g:>3} {b:>3}")
+}
+
+// Gamma corrects a linear color and quantizes it to 8 bits per channel.
+fn to_rgb8(color: &DVec3) -> [u8; 3] {
+    let intensity = Interval::new(0.000, 0.999);
+    let quantize = |c: f64| (intensity.clamp(linear_to_gamma(c)) * 256.) as u8;
+    [quantize(color.x), quantize(color.y), quantize(color.z)]
+}
+
+// Gamma corrects a linear color and quantizes it to 16 bits per channel.
+fn to_rgb16(color: &DVec3) -> [u16; 3] {
+    let intensity = Interval::new(0.0, 1.0);
+    let quantize = |c: f64| (intensity.clamp(linear_to_gamma(c)) * 65535.0).round() as u16;
+    [quantize(color.x), quantize(color.y), quantize(color.z)]
+}
+
+pub fn linear_to_gamma(linear_component: f64) -> f64 {
+    if linear_component > 0.0 {
+        return linear_component.sqrt();
+    }
+    0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The pixels of a 2x2 image with black, white, an overexposed red and a mid gray.
+    fn pixels() -> Vec<DVec3> {
+        vec![
+            DVec3::ZERO,
+            DVec3::ONE,
+            DVec3::new(4.0, 0.0, 0.0),
+            DVec3::splat(0.25),
+        ]
+    }
+
+    fn encoded(format: OutputFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode(&mut out, format, &Image::from_pixels(2, 2, pixels())).unwrap();
+        out
+    }
+
+    fn decode_exr(bytes: &[u8]) -> (Vec<exr::meta::attribute::SampleType>, Vec<[f32; 3]>) {
+        use exr::prelude::*;
+
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .specific_channels()
+            .required("R")
+            .required("G")
+            .required("B")
+            .collect_pixels(
+                |resolution, _| vec![[0.0; 3]; resolution.area()],
+                |pixels: &mut Vec<[f32; 3]>, position, (r, g, b): (f32, f32, f32)| {
+                    pixels[position.y() * 2 + position.x()] = [r, g, b]
+                },
+            )
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(Cursor::new(bytes))
+            .unwrap();
+
+        let layer = image.layer_data;
+        let (r, g, b) = &layer.channel_data.channels;
+        let types = vec![r.sample_type, g.sample_type, b.sample_type];
+        (types, layer.channel_data.pixels)
+    }
+
+    fn decode_png(bytes: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let decoder = png::Decoder::new(bytes);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        buf.truncate(info.buffer_size());
+        (info, buf)
+    }
+
+    #[test]
+    fn format_from_extension_test() {
+        let format = |p: &str| OutputFormat::from_path(Path::new(p));
+        assert_eq!(format("image.ppm"), Some(OutputFormat::PpmAscii));
+        assert_eq!(format("out/Render.PNG"), Some(OutputFormat::Png8));
+        assert_eq!(format("sky.hdr"), Some(OutputFormat::Hdr));
+        assert_eq!(
+            format("beauty.exr"),
+            Some(OutputFormat::Exr(ExrOptions {
+                precision: ExrPrecision::Half,
+                compression: ExrCompression::Zip,
+            }))
+        );
+        assert_eq!(format("image.jpg"), None);
+        assert_eq!(format("image"), None);
+    }
+
+    #[test]
+    fn ppm_ascii_test() {
+        let text = String::from_utf8(encoded(OutputFormat::PpmAscii)).unwrap();
+        assert_eq!(
+            text,
+            "P3\n2 2\n255\n  0   0   0\n255 255 255\n255   0   0\n128 128 128\n"
+        );
+    }
+
+    #[test]
+    fn ppm_binary_test() {
+        let bytes = encoded(OutputFormat::PpmBinary);
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(
+            &bytes[header.len()..],
+            &[0, 0, 0, 255, 255, 255, 255, 0, 0, 128, 128, 128]
+        );
+    }
+
+    #[test]
+    fn png8_test() {
+        let (info, data) = decode_png(&encoded(OutputFormat::Png8));
+        assert_eq!((info.width, info.height), (2, 2));
+        assert_eq!(info.bit_depth, png::BitDepth::Eight);
+        assert_eq!(data, vec![0, 0, 0, 255, 255, 255, 255, 0, 0, 128, 128, 128]);
+    }
+
+    #[test]
+    fn png16_test() {
+        let (info, data) = decode_png(&encoded(OutputFormat::Png16));
+        assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+
+        let samples: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(
+            samples,
+            vec![0, 0, 0, 65535, 65535, 65535, 65535, 0, 0, 32768, 32768, 32768]
+        );
+    }
+
+    #[test]
+    fn hdr_test() {
+        let bytes = encoded(OutputFormat::Hdr);
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 2\n";
+        assert_eq!(&bytes[..header.len()], header);
+
+        let decode = |rgbe: &[u8]| {
+            if rgbe[3] == 0 {
+                return DVec3::ZERO;
+            }
+            let scale = 2f64.powi(rgbe[3] as i32 - 136);
+            DVec3::new(rgbe[0] as f64, rgbe[1] as f64, rgbe[2] as f64) * scale
+        };
+        let decoded: Vec<DVec3> = bytes[header.len()..].chunks_exact(4).map(decode).collect();
+
+        // Values above 1 survive, to within the 8 bit mantissa.
+        for (decoded, original) in decoded.iter().zip(pixels()) {
+            assert!(
+                decoded.abs_diff_eq(original, original.max_element() / 128.0),
+                "{decoded} != {original}"
+            );
+        }
+    }
+
+    #[test]
+    fn rgbe_edge_cases_test() {
+        assert_eq!(to_rgbe(&DVec3::ZERO), [0; 4]);
+        assert_eq!(to_rgbe(&DVec3::new(-1.0, f64::NAN, 0.0)), [0; 4]);
+        // 1.0 = 0.5 * 2^1
+        assert_eq!(to_rgbe(&DVec3::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(to_rgbe(&DVec3::INFINITY), [255; 4]);
+        assert_eq!(
+            to_rgbe(&DVec3::new(f64::INFINITY, 1.0, 0.0)),
+            [255, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn exr_test() {
+        use exr::meta::attribute::SampleType;
+
+        for (precision, sample_type) in [
+            (ExrPrecision::Half, SampleType::F16),
+            (ExrPrecision::Float, SampleType::F32),
+        ] {
+            for compression in [
+                ExrCompression::None,
+                ExrCompression::Rle,
+                ExrCompression::Zip,
+                ExrCompression::Piz,
+            ] {
+                let options = ExrOptions {
+                    precision,
+                    compression,
+                };
+                let (types, decoded) = decode_exr(&encoded(OutputFormat::Exr(options)));
+
+                assert_eq!(types, vec![sample_type; 3]);
+                // The values are linear and unclamped, and exact in both precisions.
+                let expected: Vec<[f32; 3]> =
+                    pixels().iter().map(|c| c.as_vec3().to_array()).collect();
+                assert_eq!(decoded, expected, "{options:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// Perlin gradient noise, as in Ray Tracing: The Next Week.
+
+use glam::DVec3;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+#[derive(Debug)]
+pub struct Perlin {
+    // Random unit gradients at the lattice points
+    gradients: Vec<DVec3>,
+    // Independent permutations hashing each axis into the gradient table
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    // The same seed always gives the same noise.
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                DVec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalize_or(DVec3::X)
+            })
+            .collect();
+
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let perm_x = permutation();
+        let perm_y = permutation();
+        let perm_z = permutation();
+
+        Perlin {
+            gradients,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    // Smooth noise in roughly [-1, 1].
+    pub fn noise(&self, p: DVec3) -> f64 {
+        let floor = p.floor();
+        let f = p - floor;
+        let (i, j, k) = (floor.x as i64, floor.y as i64, floor.z as i64);
+
+        // Hermite smoothing hides the lattice
+        let s = f * f * (3.0 - 2.0 * f);
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[wrap(i + di)]
+                        ^ self.perm_y[wrap(j + dj)]
+                        ^ self.perm_z[wrap(k + dk)]];
+                    let weight = f - DVec3::new(di as f64, dj as f64, dk as f64);
+
+                    let (di, dj, dk) = (di as f64, dj as f64, dk as f64);
+                    accum += (di * s.x + (1.0 - di) * (1.0 - s.x))
+                        * (dj * s.y + (1.0 - dj) * (1.0 - s.y))
+                        * (dk * s.z + (1.0 - dk) * (1.0 - s.z))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // The sum of `depth` octaves of noise, each at twice the frequency and half the weight of the
+    // last. Always positive.
+    pub fn turbulence(&self, p: DVec3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+fn wrap(i: i64) -> usize {
+    (i & (POINT_COUNT as i64 - 1)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_test() {
+        let perlin = Perlin::new(7);
+        let again = Perlin::new(7);
+
+        for i in 0..1000 {
+            let p = DVec3::new(i as f64 * 0.137, -(i as f64) * 0.071, i as f64 * 0.013);
+            let n = perlin.noise(p);
+            assert!((-1.0..=1.0).contains(&n), "{n}");
+            assert_eq!(n, again.noise(p));
+            assert!(perlin.turbulence(p, 7) >= 0.0);
+        }
+
+        // Noise is zero on the lattice and continuous across it.
+        assert_eq!(perlin.noise(DVec3::new(3.0, -2.0, 5.0)), 0.0);
+        let a = perlin.noise(DVec3::new(1.0 - 1e-9, 0.5, 0.5));
+        let b = perlin.noise(DVec3::new(1.0 + 1e-9, 0.5, 0.5));
+        assert!((a - b).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,708 @@
+// A single physically based material in the style of Disney's principled BSDF, covering plastics,
+// metals, glass, lacquered and cloth-like surfaces with one set of parameters instead of one enum
+// variant each.
+//
+// Glossy reflection and refraction use the GGX microfacet distribution, sampled by its visible
+// normals so that few samples are wasted on facets facing away from the viewer. Directions are
+// worked in a frame with z along the shading normal, which faces the side the ray arrived from.
+
+use crate::hittable::HitRecord;
+use crate::image::luminance;
+use crate::material::BsdfSample;
+use crate::texture::Texture;
+use crate::utils;
+use glam::DVec3;
+use rand::Rng;
+use std::f64::consts::PI;
+
+// A roughness of exactly 0 makes a perfect mirror or pane of glass, sampled as a delta lobe. GGX
+// gets numerically unstable narrower than this, so barely rough surfaces are kept at least this
+// wide.
+const MIN_ALPHA: f64 = 1e-3;
+
+// Reflectance at normal incidence of a fully specular dielectric, about that of glass
+const MAX_DIELECTRIC_F0: f64 = 0.08;
+
+// Every parameter but `ior` runs from 0 to 1.
+#[derive(Debug, Clone)]
+pub struct Principled {
+    // Diffuse color for dielectrics, reflection color for metals and tint for transmission
+    pub base_color: Texture,
+    // Blends from a dielectric to a metal, which has no diffuse or transmission
+    pub metallic: f64,
+    // Perceptual roughness: 0 is a mirror, 1 is very rough. Squared for the GGX width.
+    pub roughness: f64,
+    // Strength of dielectric reflection; 0.5 is a typical 4% at normal incidence
+    pub specular: f64,
+    // How much of the dielectric part refracts through rather than scattering diffusely
+    pub transmission: f64,
+    // Index of refraction for transmission
+    pub ior: f64,
+    // A colorless glossy layer on top, such as lacquer or car paint
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    // Extra reflection at grazing angles, for cloth
+    pub sheen: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Principled {
+        Principled {
+            base_color: DVec3::splat(0.8).into(),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            transmission: 0.0,
+            ior: 1.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            sheen: 0.0,
+        }
+    }
+}
+
+// How much of the light a lobe gets, and how often it's sampled. The chances only depend on the
+// outgoing direction, so `pdf` can rebuild them for any incoming one.
+struct Lobes {
+    base_color: DVec3,
+    diffuse: f64,
+    transmission: f64,
+    // Normal incidence reflectance of the opaque glossy lobe, and of the dielectric part of it
+    f0: DVec3,
+    dielectric_f0: DVec3,
+    clearcoat: f64,
+    // The share of light getting through the clearcoat to the layers below
+    under_clearcoat: f64,
+    diffuse_chance: f64,
+    specular_chance: f64,
+    transmission_chance: f64,
+    clearcoat_chance: f64,
+}
+
+impl Principled {
+    // Matte, scattering like an ideal Lambertian surface.
+    pub fn diffuse(albedo: Texture) -> Principled {
+        Principled {
+            base_color: albedo,
+            roughness: 1.0,
+            specular: 0.0,
+            ..Principled::default()
+        }
+    }
+
+    // Metal tinted by `albedo`, a perfect mirror at a roughness of 0.
+    pub fn metal(albedo: Texture, roughness: f64) -> Principled {
+        Principled {
+            base_color: albedo,
+            metallic: 1.0,
+            roughness,
+            ..Principled::default()
+        }
+    }
+
+    // Clear, smooth glass, or any other dielectric with the index of refraction `ior`.
+    pub fn glass(ior: f64) -> Principled {
+        Principled {
+            base_color: DVec3::ONE.into(),
+            roughness: 0.0,
+            transmission: 1.0,
+            ior,
+            ..Principled::default()
+        }
+    }
+
+    fn alpha(roughness: f64) -> f64 {
+        (roughness * roughness).max(MIN_ALPHA)
+    }
+
+    // Whether the glossy reflection and transmission are perfectly sharp
+    fn smooth(&self) -> bool {
+        self.roughness <= 0.0
+    }
+
+    fn smooth_clearcoat(&self) -> bool {
+        self.clearcoat_roughness <= 0.0
+    }
+
+    // Whether every lobe is a delta lobe, like a mirror's or clear glass's.
+    pub fn is_specular(&self) -> bool {
+        let diffuse = (1.0 - self.metallic) * (1.0 - self.transmission);
+        diffuse <= 0.0 && self.smooth() && (self.clearcoat <= 0.0 || self.smooth_clearcoat())
+    }
+
+    // Ratio of the index of refraction past the surface to the one the ray arrives in
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ior
+        } else {
+            self.ior.recip()
+        }
+    }
+
+    fn lobes(&self, rec: &HitRecord, cos_o: f64) -> Lobes {
+        let base_color = self.base_color.value(rec.u, rec.v, rec.p);
+        let dielectric = 1.0 - self.metallic;
+        let diffuse = dielectric * (1.0 - self.transmission);
+        let transmission = dielectric * self.transmission;
+
+        let dielectric_f0 = DVec3::splat(MAX_DIELECTRIC_F0 * self.specular);
+        let f0 = dielectric_f0.lerp(base_color, self.metallic);
+        let clearcoat = 0.25 * self.clearcoat;
+        let under_clearcoat = 1.0 - clearcoat * luminance(schlick(DVec3::splat(0.04), cos_o));
+
+        // Sample roughly in proportion to what each lobe reflects from this angle.
+        let diffuse_chance = diffuse * luminance(base_color).max(0.01);
+        let specular_chance = (1.0 - transmission) * luminance(schlick(f0, cos_o));
+        let transmission_chance = transmission;
+        let clearcoat_chance = clearcoat * luminance(schlick(DVec3::splat(0.04), cos_o));
+        let total = diffuse_chance + specular_chance + transmission_chance + clearcoat_chance;
+        let total = if total > 0.0 { total } else { 1.0 };
+
+        Lobes {
+            base_color,
+            diffuse,
+            transmission,
+            f0,
+            dielectric_f0,
+            clearcoat,
+            under_clearcoat,
+            diffuse_chance: diffuse_chance / total,
+            specular_chance: specular_chance / total,
+            transmission_chance: transmission_chance / total,
+            clearcoat_chance: clearcoat_chance / total,
+        }
+    }
+
+    // Picks a direction for light to arrive from, given the unit direction `wo` it leaves along,
+    // both pointing away from the surface. None if the sample is absorbed.
+    pub fn sample(&self, wo: DVec3, rec: &HitRecord) -> Option<BsdfSample> {
+        let frame = Frame::new(rec.normal);
+        let wo_local = frame.to_local(wo);
+        if wo_local.z <= 0.0 {
+            return None;
+        }
+        let lobes = self.lobes(rec, wo_local.z);
+
+        let mut rng = utils::rng();
+        let choice = rng.gen::<f64>();
+        let u = (rng.gen::<f64>(), rng.gen::<f64>());
+        let alpha = Self::alpha(self.roughness);
+
+        let specular_start = lobes.diffuse_chance;
+        let transmission_start = specular_start + lobes.specular_chance;
+        let clearcoat_start = transmission_start + lobes.transmission_chance;
+        // Perfectly smooth lobes reflect or refract about the normal, and are weighted by what
+        // they'd integrate to if they were rough.
+        let cos_o = wo_local.z;
+        let delta = |wi_local: DVec3, weight: DVec3, pdf: f64| {
+            Some(BsdfSample::delta(frame.to_world(wi_local), weight, pdf))
+        };
+        let (wi_local, refracted) = if choice < specular_start {
+            (sample_cosine(u), false)
+        } else if choice < transmission_start {
+            if self.smooth() {
+                let f =
+                    lobes.under_clearcoat * (1.0 - lobes.transmission) * schlick(lobes.f0, cos_o);
+                let pdf = lobes.specular_chance;
+                return delta(reflect(wo_local, DVec3::Z), f / pdf, pdf);
+            }
+            (
+                reflect(wo_local, sample_visible_normal(wo_local, alpha, u)),
+                false,
+            )
+        } else if choice < clearcoat_start {
+            let h = if self.smooth() {
+                DVec3::Z
+            } else {
+                sample_visible_normal(wo_local, alpha, u)
+            };
+            // Reuse where the choice fell within the lobe to pick reflection or refraction in
+            // proportion to the Fresnel term.
+            let choice = (choice - transmission_start) / lobes.transmission_chance;
+            let eta = self.eta(rec);
+            let f = fresnel_dielectric(wo_local.dot(h), eta);
+            let (wi_local, refracted) = match refract(wo_local, h, eta) {
+                Some(wt) if choice >= f => (wt, true),
+                _ => (reflect(wo_local, h), false),
+            };
+            if self.smooth() {
+                let (tint, chance) = if refracted {
+                    (lobes.base_color, 1.0 - f)
+                } else {
+                    (DVec3::ONE, f)
+                };
+                let pdf = lobes.transmission_chance * chance;
+                let weight =
+                    lobes.under_clearcoat * lobes.transmission * tint / lobes.transmission_chance;
+                return delta(wi_local, weight, pdf);
+            }
+            (wi_local, refracted)
+        } else {
+            if self.smooth_clearcoat() {
+                let f = lobes.clearcoat * schlick(DVec3::splat(0.04), cos_o);
+                let pdf = lobes.clearcoat_chance;
+                return delta(reflect(wo_local, DVec3::Z), f / pdf, pdf);
+            }
+            let alpha = Self::alpha(self.clearcoat_roughness);
+            (
+                reflect(wo_local, sample_visible_normal(wo_local, alpha, u)),
+                false,
+            )
+        };
+
+        // A rough facet can send light back through the side it should have left by, which isn't
+        // possible, so it's absorbed.
+        if wi_local.z == 0.0 || (wi_local.z < 0.0) != refracted {
+            return None;
+        }
+        let direction = frame.to_world(wi_local);
+        let pdf = self.pdf(wo, direction, rec);
+        if pdf.is_nan() || pdf <= 0.0 {
+            return None;
+        }
+        let weight = self.eval(wo, direction, rec) / pdf;
+        Some(BsdfSample {
+            direction,
+            weight,
+            pdf,
+            delta: false,
+        })
+    }
+
+    // The fraction of light arriving along the unit direction `wi` that leaves along `wo`,
+    // including the cosine at the surface.
+    pub fn eval(&self, wo: DVec3, wi: DVec3, rec: &HitRecord) -> DVec3 {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(wo);
+        let wi = frame.to_local(wi);
+        let (cos_o, cos_i) = (wo.z, wi.z);
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return DVec3::ZERO;
+        }
+        let lobes = self.lobes(rec, cos_o);
+        let alpha = Self::alpha(self.roughness);
+
+        if cos_i < 0.0 {
+            if lobes.transmission <= 0.0 || self.smooth() {
+                return DVec3::ZERO;
+            }
+            let eta = self.eta(rec);
+            let Some(h) = refraction_half_vector(wo, wi, eta) else {
+                return DVec3::ZERO;
+            };
+            let (o_h, i_h) = (wo.dot(h), wi.dot(h));
+            let f = fresnel_dielectric(o_h, eta);
+            let denom = i_h + o_h / eta;
+            let btdf = (1.0 - f) * ggx_d(h, alpha) * ggx_g2(wo, wi, alpha) * (i_h * o_h).abs()
+                / (cos_o * denom * denom);
+            // The cosine at the surface cancels the one in the denominator.
+            return lobes.under_clearcoat * lobes.transmission * lobes.base_color * btdf;
+        }
+
+        let h = (wo + wi).normalize();
+        let o_h = wo.dot(h);
+        let mut f = DVec3::ZERO;
+
+        if lobes.diffuse > 0.0 {
+            // Lambertian, under the dielectric reflection, plus sheen at grazing angles
+            let under_specular = 1.0 - luminance(schlick(lobes.dielectric_f0, cos_o));
+            let sheen = self.sheen * schlick_weight(o_h);
+            f += lobes.diffuse * under_specular * lobes.base_color * (1.0 / PI + sheen) * cos_i;
+        }
+
+        if !self.smooth() {
+            let g_spec = ggx_d(h, alpha) * ggx_g2(wo, wi, alpha) / (4.0 * cos_o);
+            f += (1.0 - lobes.transmission) * schlick(lobes.f0, o_h) * g_spec;
+            if lobes.transmission > 0.0 {
+                let fresnel = fresnel_dielectric(o_h, self.eta(rec));
+                f += lobes.transmission * fresnel * g_spec;
+            }
+        }
+
+        f *= lobes.under_clearcoat;
+        if lobes.clearcoat > 0.0 && !self.smooth_clearcoat() {
+            let alpha = Self::alpha(self.clearcoat_roughness);
+            let g_coat = ggx_d(h, alpha) * ggx_g2(wo, wi, alpha) / (4.0 * cos_o);
+            f += lobes.clearcoat * schlick(DVec3::splat(0.04), o_h) * g_coat;
+        }
+
+        f
+    }
+
+    // Probability density, per unit solid angle, that `sample` picks the unit direction `wi`.
+    pub fn pdf(&self, wo: DVec3, wi: DVec3, rec: &HitRecord) -> f64 {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(wo);
+        let wi = frame.to_local(wi);
+        let (cos_o, cos_i) = (wo.z, wi.z);
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return 0.0;
+        }
+        let lobes = self.lobes(rec, cos_o);
+        let alpha = Self::alpha(self.roughness);
+        let eta = self.eta(rec);
+
+        if cos_i < 0.0 {
+            if lobes.transmission_chance <= 0.0 || self.smooth() {
+                return 0.0;
+            }
+            let Some(h) = refraction_half_vector(wo, wi, eta) else {
+                return 0.0;
+            };
+            let (o_h, i_h) = (wo.dot(h), wi.dot(h));
+            let denom = i_h + o_h / eta;
+            let jacobian = i_h.abs() / (denom * denom);
+            let t = 1.0 - fresnel_dielectric(o_h, eta);
+            return lobes.transmission_chance * t * visible_normal_pdf(wo, h, alpha) * jacobian;
+        }
+
+        let h = (wo + wi).normalize();
+        let o_h = wo.dot(h);
+        // Turns the density of reflecting normals into one of reflected directions
+        let jacobian = 1.0 / (4.0 * o_h);
+        let glossy = visible_normal_pdf(wo, h, alpha) * jacobian;
+
+        let mut pdf = lobes.diffuse_chance * cos_i / PI;
+        if !self.smooth() {
+            pdf += lobes.specular_chance * glossy;
+            if lobes.transmission_chance > 0.0 {
+                pdf += lobes.transmission_chance * fresnel_dielectric(o_h, eta) * glossy;
+            }
+        }
+        if lobes.clearcoat_chance > 0.0 && !self.smooth_clearcoat() {
+            let alpha = Self::alpha(self.clearcoat_roughness);
+            pdf += lobes.clearcoat_chance * visible_normal_pdf(wo, h, alpha) * jacobian;
+        }
+        pdf
+    }
+}
+
+// An orthonormal basis with z along a normal.
+struct Frame {
+    t: DVec3,
+    b: DVec3,
+    n: DVec3,
+}
+
+impl Frame {
+    fn new(n: DVec3) -> Frame {
+        let n = n.normalize();
+        let (t, b) = n.any_orthonormal_pair();
+        Frame { t, b, n }
+    }
+
+    fn to_local(&self, v: DVec3) -> DVec3 {
+        DVec3::new(v.dot(self.t), v.dot(self.b), v.dot(self.n))
+    }
+
+    fn to_world(&self, v: DVec3) -> DVec3 {
+        v.x * self.t + v.y * self.b + v.z * self.n
+    }
+}
+
+fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos).clamp(0.0, 1.0).powi(5)
+}
+
+// Schlick's Fresnel approximation. Reflectance rises to white at grazing angles, except where f0
+// is next to nothing, so that `specular = 0` turns reflection off entirely.
+fn schlick(f0: DVec3, cos: f64) -> DVec3 {
+    let f90 = (50.0 * luminance(f0)).clamp(0.0, 1.0);
+    f0 + (DVec3::splat(f90) - f0) * schlick_weight(cos)
+}
+
+// The fraction of unpolarized light reflected where the cosine between the ray leaving and the
+// microfacet normal is `cos_i`, going into a medium `eta` times as dense.
+fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(-1.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let cos_i = cos_i.abs();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+fn reflect(wo: DVec3, h: DVec3) -> DVec3 {
+    -wo + 2.0 * wo.dot(h) * h
+}
+
+// Bends `wo` through a microfacet with normal `h` into a medium `eta` times as dense, or None past
+// the critical angle.
+fn refract(wo: DVec3, h: DVec3, eta: f64) -> Option<DVec3> {
+    let cos_i = wo.dot(h);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * h)
+}
+
+// The microfacet normal that refracts `wi` into `wo`, facing the outgoing side, or None if they're
+// on the same side of it.
+fn refraction_half_vector(wo: DVec3, wi: DVec3, eta: f64) -> Option<DVec3> {
+    let h = wo + wi * eta;
+    if h.length_squared() == 0.0 {
+        return None;
+    }
+    let h = h.normalize();
+    let h = if h.z < 0.0 { -h } else { h };
+    // Both directions have to see the facet from their own side of it.
+    if wo.dot(h) <= 0.0 || wi.dot(h) >= 0.0 {
+        return None;
+    }
+    Some(h)
+}
+
+// GGX distribution of microfacet normals, as a density over the projected area
+fn ggx_d(h: DVec3, alpha: f64) -> f64 {
+    if h.z <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let d = h.z * h.z * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+fn ggx_lambda(w: DVec3, alpha: f64) -> f64 {
+    let cos2 = w.z * w.z;
+    if cos2 == 0.0 {
+        return f64::INFINITY;
+    }
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    ((1.0 + alpha * alpha * tan2).sqrt() - 1.0) / 2.0
+}
+
+// Fraction of facets seen from `w` that aren't hidden by others
+fn ggx_g1(w: DVec3, alpha: f64) -> f64 {
+    1.0 / (1.0 + ggx_lambda(w, alpha))
+}
+
+// Fraction of facets seen from both directions, shadowing and masking correlated
+fn ggx_g2(wo: DVec3, wi: DVec3, alpha: f64) -> f64 {
+    1.0 / (1.0 + ggx_lambda(wo, alpha) + ggx_lambda(wi, alpha))
+}
+
+// Density of `sample_visible_normal` picking the normal h seen from wo
+fn visible_normal_pdf(wo: DVec3, h: DVec3, alpha: f64) -> f64 {
+    let o_h = wo.dot(h);
+    if o_h <= 0.0 {
+        return 0.0;
+    }
+    ggx_g1(wo, alpha) * o_h * ggx_d(h, alpha) / wo.z
+}
+
+// A microfacet normal drawn in proportion to how much of it wo sees, after Heitz, "Sampling the
+// GGX Distribution of Visible Normals", 2018.
+fn sample_visible_normal(wo: DVec3, alpha: f64, (u1, u2): (f64, f64)) -> DVec3 {
+    // Stretch to the configuration where the distribution is a hemisphere.
+    let v = DVec3::new(alpha * wo.x, alpha * wo.y, wo.z).normalize();
+    let len2 = v.x * v.x + v.y * v.y;
+    let t1 = if len2 > 0.0 {
+        DVec3::new(-v.y, v.x, 0.0) / len2.sqrt()
+    } else {
+        DVec3::X
+    };
+    let t2 = v.cross(t1);
+
+    // A point on the disk, squashed onto the part of it the view sees
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + v.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+
+    let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+    DVec3::new(alpha * n.x, alpha * n.y, n.z.max(0.0)).normalize()
+}
+
+fn sample_cosine((u1, u2): (f64, f64)) -> DVec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    DVec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random_dvec3_unit;
+
+    fn rec(front_face: bool) -> HitRecord<'static> {
+        HitRecord {
+            normal: DVec3::Y,
+            front_face,
+            ..Default::default()
+        }
+    }
+
+    fn materials() -> Vec<(&'static str, Principled)> {
+        vec![
+            (
+                "diffuse",
+                Principled::diffuse(DVec3::new(0.8, 0.4, 0.2).into()),
+            ),
+            ("plastic", Principled::default()),
+            (
+                "metal",
+                Principled::metal(DVec3::new(0.9, 0.6, 0.3).into(), 0.4),
+            ),
+            (
+                "rough glass",
+                Principled {
+                    roughness: 0.5,
+                    ..Principled::glass(1.5)
+                },
+            ),
+            (
+                "lacquer",
+                Principled {
+                    clearcoat: 1.0,
+                    clearcoat_roughness: 0.2,
+                    sheen: 0.5,
+                    ..Principled::default()
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn sample_matches_eval_and_pdf_test() {
+        let wo = DVec3::new(0.3, 0.8, -0.2).normalize();
+        for front_face in [true, false] {
+            let rec = rec(front_face);
+            for (name, mat) in materials() {
+                for _ in 0..1000 {
+                    let Some(s) = mat.sample(wo, &rec) else {
+                        continue;
+                    };
+                    assert!((s.direction.length() - 1.0).abs() < 1e-9, "{name}");
+                    let pdf = mat.pdf(wo, s.direction, &rec);
+                    assert!((pdf - s.pdf).abs() <= 1e-9 * pdf, "{name}");
+                    let weight = mat.eval(wo, s.direction, &rec) / pdf;
+                    assert!(weight.abs_diff_eq(s.weight, 1e-9), "{name}");
+                    assert!(weight.min_element() >= 0.0, "{name}: {weight}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_test() {
+        // Uniform directions over the sphere estimate the integral of each pdf, from a fixed
+        // stream. The lobes have to be wide enough for that to converge.
+        let wo = DVec3::new(0.5, 0.7, 0.1).normalize();
+        let rec = rec(true);
+        utils::reseed(1, 0, 0);
+        let samples = 500_000;
+        for (name, mat) in materials() {
+            let total: f64 = (0..samples)
+                .map(|_| mat.pdf(wo, random_dvec3_unit(), &rec))
+                .sum();
+            let integral = total / samples as f64 * 4.0 * PI;
+            // Total internal reflection bounces some would-be refractions back outside the
+            // sampled lobe, so glass can come up short.
+            assert!(integral > 0.9 && integral < 1.1, "{name}: {integral}");
+        }
+    }
+
+    #[test]
+    fn energy_conservation_test() {
+        // A white furnace: nothing reflects more light than it receives. The estimates come from a
+        // fixed stream so the bound isn't missed by bad luck.
+        let rec = rec(true);
+        utils::reseed(2, 0, 0);
+        for cos_o in [0.1f64, 0.5, 1.0] {
+            let wo = DVec3::new((1.0 - cos_o * cos_o).sqrt(), cos_o, 0.0);
+            for (name, mut mat) in materials() {
+                mat.base_color = DVec3::ONE.into();
+                let samples = 20_000;
+                let mut total = DVec3::ZERO;
+                for _ in 0..samples {
+                    if let Some(s) = mat.sample(wo, &rec) {
+                        total += s.weight / samples as f64;
+                    }
+                }
+                assert!(total.max_element() < 1.05, "{name} at {cos_o}: {total}");
+            }
+        }
+    }
+
+    #[test]
+    fn presets_test() {
+        // The counts below are of random samples, so draw them from a fixed stream.
+        utils::reseed(3, 0, 0);
+        let rec = rec(true);
+        let wo = DVec3::new(1.0, 1.0, 0.0).normalize();
+
+        // Matte scatters like a Lambertian surface, nearly.
+        let diffuse = Principled::diffuse(DVec3::splat(0.5).into());
+        let straight_up = diffuse.eval(wo, DVec3::Y, &rec);
+        assert!((straight_up.x - 0.5 / PI).abs() < 0.03, "{straight_up}");
+        assert_eq!(diffuse.eval(wo, -DVec3::Y, &rec), DVec3::ZERO);
+
+        // A smooth metal is a mirror tinted by its color, nearly exactly at 45 degrees, where
+        // Schlick's term has barely started to whiten it.
+        let mirror = Principled::metal(DVec3::new(0.9, 0.5, 0.1).into(), 0.0);
+        assert!(mirror.is_specular());
+        let reflected = DVec3::new(-1.0, 1.0, 0.0).normalize();
+        for _ in 0..100 {
+            let s = mirror.sample(wo, &rec).unwrap();
+            assert!(s.delta);
+            assert_eq!(s.pdf, 1.0);
+            assert!((s.direction - reflected).length() < 1e-12);
+            assert!(s.weight.abs_diff_eq(DVec3::new(0.9, 0.5, 0.1), 0.01));
+        }
+        assert_eq!(mirror.pdf(wo, reflected, &rec), 0.0);
+        assert_eq!(mirror.eval(wo, reflected, &rec), DVec3::ZERO);
+
+        // Smooth glass refracts exactly as Snell's law says, or reflects.
+        let glass = Principled::glass(1.5);
+        assert!(glass.is_specular());
+        let refracted = DVec3::new(-(2.0f64.sqrt()) / 3.0, -(7.0f64.sqrt()) / 3.0, 0.0);
+        let mut through = 0;
+        for _ in 0..1000 {
+            let s = glass.sample(wo, &rec).unwrap();
+            assert!(s.delta);
+            assert_eq!(s.weight, DVec3::ONE);
+            if s.direction.y < 0.0 {
+                through += 1;
+                assert!(
+                    (s.direction - refracted).length() < 1e-12,
+                    "{}",
+                    s.direction
+                );
+            } else {
+                assert!(
+                    (s.direction - reflected).length() < 1e-12,
+                    "{}",
+                    s.direction
+                );
+            }
+        }
+        // Glass reflects about 5% at 45 degrees.
+        assert!((900..=990).contains(&through), "{through}");
+
+        // Smooth plastic mixes a sharp highlight with a matte base, so it isn't specular, and
+        // only the base is seen by `eval` and `pdf`.
+        let plastic = Principled {
+            roughness: 0.0,
+            ..Principled::default()
+        };
+        assert!(!plastic.is_specular());
+        let mut highlights = 0;
+        for _ in 0..1000 {
+            let s = plastic.sample(wo, &rec).unwrap();
+            if s.delta {
+                highlights += 1;
+                assert!((s.direction - reflected).length() < 1e-12);
+            } else {
+                assert!((plastic.pdf(wo, s.direction, &rec) - s.pdf).abs() < 1e-9);
+            }
+        }
+        assert!((1..1000).contains(&highlights), "{highlights}");
+    }
+}
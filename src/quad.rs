@@ -0,0 +1,363 @@
+use crate::aabb::AABB;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utils;
+use glam::DVec3;
+use rand::Rng;
+use std::f64;
+
+// Which part of its plane a `Quad` covers, in terms of the plane coordinates (alpha, beta) of a
+// point q + alpha * u + beta * v.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    // The parallelogram with corners q, q + u, q + v and q + u + v
+    Parallelogram,
+    // The triangle with corners q, q + u and q + v
+    Triangle,
+    // The ellipse centered on q with semi-axes u and v
+    Ellipse,
+}
+
+// A flat shape on the plane through `q` spanned by `u` and `v`. Quads, triangles, disks and
+// ellipses all share the same ray/plane intersection and differ only in the interior test.
+// The front face is the one u x v points out of.
+#[derive(Clone)]
+pub struct Quad {
+    q: DVec3,
+    u: DVec3,
+    v: DVec3,
+    // u x v scaled by its squared length, which turns a point on the plane into (alpha, beta)
+    w: DVec3,
+    normal: DVec3,
+    // The plane is the set of points p with normal . p = d
+    d: f64,
+    shape: Shape,
+    mat: Material,
+    bbox: AABB,
+    area: f64,
+}
+
+impl Quad {
+    // The parallelogram with corner `q` and edges `u` and `v`.
+    pub fn new(q: DVec3, u: DVec3, v: DVec3, mat: Material) -> Quad {
+        let bbox = AABB::from_boxes(
+            &AABB::from_points(q, q + u + v),
+            &AABB::from_points(q + u, q + v),
+        );
+        Quad::with_shape(q, u, v, Shape::Parallelogram, bbox, mat)
+    }
+
+    // The triangle with corner `q` and edges `u` and `v`. Meshes use `Triangle`, which is
+    // watertight along shared edges; this is for standalone shapes that want the quad's UVs.
+    pub fn triangle(q: DVec3, u: DVec3, v: DVec3, mat: Material) -> Quad {
+        let bbox = AABB::from_boxes(
+            &AABB::from_points(q, q + u),
+            &AABB::from_points(q + v, q + v),
+        );
+        Quad::with_shape(q, u, v, Shape::Triangle, bbox, mat)
+    }
+
+    // The ellipse centered on `center` with semi-axes `u` and `v`.
+    pub fn ellipse(center: DVec3, u: DVec3, v: DVec3, mat: Material) -> Quad {
+        // Along each axis the ellipse reaches sqrt(u_i^2 + v_i^2) either side of the center.
+        let extent = (u * u + v * v).powf(0.5);
+        let bbox = AABB::from_points(center - extent, center + extent);
+        Quad::with_shape(center, u, v, Shape::Ellipse, bbox, mat)
+    }
+
+    // The circle of `radius` around `center`, facing along `normal`.
+    pub fn disk(center: DVec3, normal: DVec3, radius: f64, mat: Material) -> Quad {
+        let (u, v) = normal.normalize().any_orthonormal_pair();
+        Quad::ellipse(center, u * radius, v * radius, mat)
+    }
+
+    fn with_shape(q: DVec3, u: DVec3, v: DVec3, shape: Shape, bbox: AABB, mat: Material) -> Quad {
+        let n = u.cross(v);
+        let normal = n.normalize();
+        let area = match shape {
+            Shape::Parallelogram => n.length(),
+            Shape::Triangle => 0.5 * n.length(),
+            Shape::Ellipse => f64::consts::PI * n.length(),
+        };
+
+        Quad {
+            q,
+            u,
+            v,
+            w: n / n.dot(n),
+            normal,
+            d: normal.dot(q),
+            shape,
+            mat,
+            bbox: bbox.pad_to_minimums(),
+            area,
+        }
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    // Whether the plane coordinates (alpha, beta) fall inside the shape, and if so the surface
+    // coordinates there. Ellipses map their bounding square onto the unit square.
+    fn interior(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        let unit = |x: f64| (0.0..=1.0).contains(&x);
+        match self.shape {
+            Shape::Parallelogram => (unit(alpha) && unit(beta)).then_some((alpha, beta)),
+            Shape::Triangle => {
+                (alpha >= 0.0 && beta >= 0.0 && alpha + beta <= 1.0).then_some((alpha, beta))
+            }
+            Shape::Ellipse => (alpha * alpha + beta * beta <= 1.0)
+                .then_some((0.5 * (alpha + 1.0), 0.5 * (beta + 1.0))),
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(r.direction);
+
+        // The ray runs parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(r.origin)) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        // Find where on the plane the ray lands, in terms of u and v.
+        let p = r.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        let (u, v) = self.interior(alpha, beta)?;
+
+        let mut rec = HitRecord {
+            p,
+            t,
+            u,
+            v,
+            mat: &self.mat,
+            ..Default::default()
+        };
+        rec.set_face_normal(r, self.normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bbox)
+    }
+
+    // Converts the uniform density over the shape's area into one over solid angle.
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        let ray = Ray::with_direction(origin, direction);
+        let Some(rec) = self.hit(&ray, Interval::new(0.001, f64::INFINITY)) else {
+            return 0.0;
+        };
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = direction.normalize().dot(self.normal).abs();
+        if self.area == 0.0 || cosine == 0.0 {
+            return 0.0;
+        }
+        distance_squared / (cosine * self.area)
+    }
+
+    // Aims at a point picked uniformly over the shape's area.
+    fn random(&self, origin: DVec3) -> DVec3 {
+        let mut rng = utils::rng();
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+
+        let (alpha, beta) = match self.shape {
+            Shape::Parallelogram => (r1, r2),
+            // Fold the far half of the parallelogram back onto the triangle
+            Shape::Triangle if r1 + r2 > 1.0 => (1.0 - r1, 1.0 - r2),
+            Shape::Triangle => (r1, r2),
+            Shape::Ellipse => {
+                let radius = r1.sqrt();
+                let phi = 2.0 * f64::consts::PI * r2;
+                (radius * phi.cos(), radius * phi.sin())
+            }
+        };
+        self.q + alpha * self.u + beta * self.v - origin
+    }
+}
+
+// The six faces of the box with opposite corners `a` and `b`, facing outwards.
+pub fn make_box(a: DVec3, b: DVec3, mat: Material) -> [Quad; 6] {
+    let min = a.min(b);
+    let max = a.max(b);
+
+    let dx = DVec3::new(max.x - min.x, 0.0, 0.0);
+    let dy = DVec3::new(0.0, max.y - min.y, 0.0);
+    let dz = DVec3::new(0.0, 0.0, max.z - min.z);
+
+    [
+        Quad::new(DVec3::new(min.x, min.y, max.z), dx, dy, mat.clone()), // front
+        Quad::new(DVec3::new(max.x, min.y, max.z), -dz, dy, mat.clone()), // right
+        Quad::new(DVec3::new(max.x, min.y, min.z), -dx, dy, mat.clone()), // back
+        Quad::new(DVec3::new(min.x, min.y, min.z), dz, dy, mat.clone()), // left
+        Quad::new(DVec3::new(min.x, max.y, max.z), dx, -dz, mat.clone()), // top
+        Quad::new(DVec3::new(min.x, min.y, min.z), dx, dz, mat),         // bottom
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random_dvec3_unit;
+
+    fn white() -> Material {
+        Material::lambertian(DVec3::ONE)
+    }
+
+    fn forward() -> Interval {
+        Interval::new(0.001, f64::INFINITY)
+    }
+
+    #[test]
+    fn interior_test() {
+        // Each shape spans [0, 2] x [0, 2] on the z = -1 plane, or [-1, 1] x [-1, 1] for ellipses.
+        let quad = Quad::new(
+            DVec3::new(0.0, 0.0, -1.0),
+            2.0 * DVec3::X,
+            2.0 * DVec3::Y,
+            white(),
+        );
+        let triangle = Quad::triangle(
+            DVec3::new(0.0, 0.0, -1.0),
+            2.0 * DVec3::X,
+            2.0 * DVec3::Y,
+            white(),
+        );
+        let disk = Quad::disk(DVec3::new(0.0, 0.0, -1.0), DVec3::Z, 1.0, white());
+
+        fn hits(shape: &Quad, x: f64, y: f64) -> Option<HitRecord<'_>> {
+            let r = Ray::with_direction(DVec3::new(x, y, 0.0), -DVec3::Z);
+            shape.hit(&r, forward())
+        }
+
+        let rec = hits(&quad, 0.5, 1.5).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, DVec3::Z);
+        assert!(hits(&quad, 1.9, 1.9).is_some());
+        assert!(hits(&quad, 2.1, 1.0).is_none());
+        assert!(hits(&quad, -0.1, 1.0).is_none());
+
+        assert!(hits(&triangle, 0.9, 0.9).is_some());
+        assert!(hits(&triangle, 1.1, 1.1).is_none());
+
+        assert!(hits(&disk, 0.0, 0.0).is_some());
+        assert!(hits(&disk, 0.75, 0.75).is_none());
+        assert!(hits(&disk, 0.0, 0.99).is_some());
+        assert!(hits(&disk, 0.0, 1.01).is_none());
+        assert!(
+            disk.hit(&Ray::with_direction(DVec3::ZERO, -DVec3::Z), forward())
+                .unwrap()
+                .front_face
+        );
+
+        // Parallel to the plane
+        let r = Ray::with_direction(DVec3::new(0.5, 0.5, -1.0), DVec3::X);
+        assert!(quad.hit(&r, forward()).is_none());
+    }
+
+    #[test]
+    fn bounding_box_test() {
+        let ellipse = Quad::ellipse(
+            DVec3::ZERO,
+            DVec3::new(2.0, 0.0, 0.0),
+            DVec3::new(0.0, 1.0, 1.0),
+            white(),
+        );
+        let bbox = ellipse.bounding_box().unwrap();
+        assert!((bbox.x.max - 2.0).abs() < 1e-12);
+        assert!((bbox.y.max - 1.0).abs() < 1e-12);
+        assert!((bbox.z.min + 1.0).abs() < 1e-12);
+
+        // Flat shapes still get some thickness.
+        let quad = Quad::new(DVec3::ZERO, DVec3::X, DVec3::Y, white());
+        assert!(quad.bounding_box().unwrap().z.size() > 0.0);
+    }
+
+    #[test]
+    fn light_pdf_test() {
+        let origin = DVec3::ZERO;
+        let shapes = [
+            Quad::new(
+                DVec3::new(-1.0, 2.0, -1.0),
+                2.0 * DVec3::X,
+                DVec3::new(0.0, 0.5, 2.0),
+                white(),
+            ),
+            Quad::triangle(
+                DVec3::new(-1.0, 2.0, -1.0),
+                2.0 * DVec3::X,
+                2.0 * DVec3::Z,
+                white(),
+            ),
+            Quad::disk(
+                DVec3::new(0.0, 2.0, 0.0),
+                DVec3::new(0.0, -1.0, 0.3),
+                1.0,
+                white(),
+            ),
+        ];
+
+        for shape in shapes {
+            // Every sampled direction lands on the shape.
+            for _ in 0..100 {
+                let direction = shape.random(origin);
+                assert!(shape.pdf_value(origin, direction) > 0.0);
+            }
+
+            // The density integrates to one over the sphere of directions.
+            let samples = 200_000;
+            let total: f64 = (0..samples)
+                .map(|_| shape.pdf_value(origin, random_dvec3_unit()))
+                .sum();
+            let integral = total / samples as f64 * 4.0 * f64::consts::PI;
+            assert!(
+                (integral - 1.0).abs() < 0.03,
+                "{:?}: {integral}",
+                shape.shape()
+            );
+        }
+    }
+
+    #[test]
+    fn make_box_test() {
+        let faces = make_box(DVec3::new(1.0, 2.0, 3.0), DVec3::ZERO, white());
+        let center = DVec3::new(0.5, 1.0, 1.5);
+
+        let mut area = 0.0;
+        for face in &faces {
+            area += face.area();
+            // Every face is hit from outside on its front.
+            let r = Ray::with_direction(center + 4.0 * face.normal, -face.normal);
+            let rec = face.hit(&r, forward()).unwrap();
+            assert!(rec.front_face);
+        }
+        assert!((area - 2.0 * (2.0 + 3.0 + 6.0)).abs() < 1e-12);
+
+        // From inside, a ray leaves through exactly one face.
+        let r = Ray::with_direction(center, DVec3::new(0.3, 0.2, 0.1));
+        let exits = faces
+            .iter()
+            .filter(|face| face.hit(&r, forward()).is_some())
+            .count();
+        assert_eq!(exits, 1);
+    }
+}
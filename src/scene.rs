@@ -0,0 +1,1556 @@
+// Scene description files.
+//
+// Scenes are written in TOML: an optional `[camera]` table, named materials under
+// `[materials.<name>]`, and an `[[objects]]` array referring to those materials by name. Relative
+// mesh paths are resolved against the directory holding the scene file. See `scenes/` for
+// examples.
+
+use crate::camera::{Background, Camera};
+use crate::hittable::{Hittable, HittableList};
+use crate::instance::Transform;
+use crate::material::Material;
+use crate::medium::ConstantMedium;
+use crate::obj;
+use crate::principled::Principled;
+use crate::quad::{self, Quad};
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::stl::{self, StlOptions};
+use crate::texture::{Filter, ImageTexture, NoiseStyle, Texture, WrapMode};
+use crate::triangle::Triangle;
+use crate::volume::{GridVolume, VoxelGrid};
+use glam::{DMat4, DQuat, DVec3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    // Syntax errors and values of the wrong type; the message points at the offending line.
+    Parse(toml::de::Error),
+    // Values that are well formed but don't make sense, such as a missing material.
+    Invalid {
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            SceneError::Parse(e) => write!(f, "{e}"),
+            SceneError::Invalid { key, message } => write!(f, "`{key}`: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Parse(e) => Some(e),
+            SceneError::Invalid { .. } => None,
+        }
+    }
+}
+
+fn invalid(key: impl Into<String>, message: impl Into<String>) -> SceneError {
+    SceneError::Invalid {
+        key: key.into(),
+        message: message.into(),
+    }
+}
+
+// Also rejects NaN, unlike `x <= 0.0`.
+fn is_positive(x: f64) -> bool {
+    x > 0.0
+}
+
+// Media scatter light through a phase function, which only these materials describe.
+fn is_phase_function(mat: &Material) -> bool {
+    matches!(
+        mat,
+        Material::Isotropic { .. } | Material::HenyeyGreenstein { .. }
+    )
+}
+
+// The six faces of a box with corners `a` and `b`, which must span some distance on every axis.
+fn build_box(key: &str, a: DVec3, b: DVec3, mat: Material) -> Result<HittableList, SceneError> {
+    let size = (b - a).abs();
+    if size.min_element() == 0.0 {
+        return Err(invalid(
+            format!("{key}.b"),
+            "must differ from a along every axis",
+        ));
+    }
+    let mut faces = HittableList::new();
+    for face in quad::make_box(a, b, mat) {
+        faces
+            .add(Box::new(face))
+            .map_err(|e| invalid(key, e.to_string()))?;
+    }
+    Ok(faces)
+}
+
+pub struct Scene {
+    pub world: HittableList,
+    // Copies of the emissive spheres, triangles and flat shapes in the world, for the camera to
+    // sample
+    pub lights: HittableList,
+    pub camera: Camera,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    camera: CameraDesc,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectEntry>,
+}
+
+// Every camera setting is optional and falls back to the `Camera::new` default.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    aspect_ratio: Option<f32>,
+    image_width: Option<i32>,
+    samples_per_pixel: Option<i32>,
+    max_depth: Option<i32>,
+    vfov: Option<f64>,
+    look_from: Option<DVec3>,
+    look_at: Option<DVec3>,
+    look_up: Option<DVec3>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    // A solid color for escaping rays instead of the sky gradient
+    background: Option<DVec3>,
+    // Makes the render repeatable
+    seed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: TextureDesc,
+    },
+    Metal {
+        albedo: TextureDesc,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Dielectric {
+        refraction_index: f64,
+    },
+    // Scatters evenly in every direction, for `medium` objects
+    Isotropic {
+        albedo: TextureDesc,
+    },
+    // Scatters mostly forwards for positive `g` and backwards for negative, for `medium` and
+    // `volume` objects. Volumes with an emission grid glow in the `emit` color.
+    HenyeyGreenstein {
+        albedo: TextureDesc,
+        #[serde(default)]
+        g: f64,
+        emit: Option<TextureDesc>,
+    },
+    // The one material that covers the rest. Anything left out takes its value from
+    // `Principled::default()`.
+    Principled {
+        base_color: TextureDesc,
+        metallic: Option<f64>,
+        roughness: Option<f64>,
+        specular: Option<f64>,
+        transmission: Option<f64>,
+        ior: Option<f64>,
+        clearcoat: Option<f64>,
+        clearcoat_roughness: Option<f64>,
+        sheen: Option<f64>,
+    },
+    DiffuseLight {
+        emit: TextureDesc,
+        #[serde(default)]
+        two_sided: bool,
+    },
+}
+
+// Either a plain color, written as an array, or a table describing a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureDesc {
+    Color(DVec3),
+    Texture(TextureKindDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureKindDesc {
+    Checker {
+        size: f64,
+        even: Box<TextureDesc>,
+        odd: Box<TextureDesc>,
+    },
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        wrap: WrapMode,
+        #[serde(default)]
+        filter: Filter,
+    },
+    Noise {
+        #[serde(default = "unit_scale")]
+        scale: f64,
+        #[serde(default)]
+        style: NoiseStyle,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+// An object and where to put it. The object is scaled, then turned about the y axis, then moved.
+#[derive(Deserialize)]
+struct ObjectEntry {
+    #[serde(flatten)]
+    object: ObjectDesc,
+    scale: Option<DVec3>,
+    // Degrees, counter-clockwise seen from above
+    rotate_y: Option<f64>,
+    translate: Option<DVec3>,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: DVec3,
+        radius: f64,
+        // Distance moved over the shutter interval, for motion blur
+        velocity: Option<DVec3>,
+        material: String,
+    },
+    Triangle {
+        vertices: [DVec3; 3],
+        normals: Option<[DVec3; 3]>,
+        material: String,
+    },
+    // The parallelogram with corner `q` and edges `u` and `v`
+    Quad {
+        q: DVec3,
+        u: DVec3,
+        v: DVec3,
+        material: String,
+    },
+    Disk {
+        center: DVec3,
+        normal: DVec3,
+        radius: f64,
+        material: String,
+    },
+    // Semi-axes `u` and `v`
+    Ellipse {
+        center: DVec3,
+        u: DVec3,
+        v: DVec3,
+        material: String,
+    },
+    // Six quads between opposite corners `a` and `b`
+    Box {
+        a: DVec3,
+        b: DVec3,
+        material: String,
+    },
+    // Fog or smoke filling the boundary, scattering `density` of the light per unit distance
+    Medium {
+        boundary: BoundaryDesc,
+        density: f64,
+        material: String,
+    },
+    // Smoke or cloud of varying density, read from a `.vol` grid and scaled by `density`. An
+    // optional `emission` grid makes it glow.
+    Volume {
+        path: PathBuf,
+        #[serde(default = "unit_scale")]
+        density: f64,
+        emission: Option<PathBuf>,
+        material: String,
+    },
+    Obj {
+        path: PathBuf,
+        material: String,
+    },
+    Stl {
+        path: PathBuf,
+        weld_tolerance: Option<f64>,
+        crease_angle: Option<f64>,
+        material: String,
+    },
+}
+
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    parse_scene(&text, base_dir)
+}
+
+// Builds a scene from TOML text, resolving relative mesh paths against `base_dir`.
+pub fn parse_scene(text: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    let desc: SceneDesc = toml::from_str(text).map_err(SceneError::Parse)?;
+
+    let camera = desc.camera.build()?;
+
+    let mut materials = BTreeMap::new();
+    for (name, mat) in desc.materials.iter() {
+        materials.insert(
+            name.as_str(),
+            mat.build(&format!("materials.{name}"), base_dir)?,
+        );
+    }
+
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+    // Meshes and volumes described the same way are loaded once and shared by every placement of
+    // them.
+    let mut meshes: Vec<(&ObjectDesc, Arc<dyn Hittable>)> = Vec::new();
+    for (i, entry) in desc.objects.iter().enumerate() {
+        let key = format!("objects[{i}]");
+        let object = &entry.object;
+
+        let placed = if matches!(
+            object,
+            ObjectDesc::Obj { .. } | ObjectDesc::Stl { .. } | ObjectDesc::Volume { .. }
+        ) {
+            let mesh = match meshes.iter().find(|(desc, _)| *desc == object) {
+                Some((_, mesh)) => mesh.clone(),
+                None => {
+                    let mesh: Arc<dyn Hittable> = object.build(&key, &materials, base_dir)?.into();
+                    meshes.push((object, mesh.clone()));
+                    mesh
+                }
+            };
+            entry.place(&key, mesh)?
+        } else {
+            entry.place(&key, object.build(&key, &materials, base_dir)?)?
+        };
+        world
+            .add(placed)
+            .map_err(|e| invalid(&key, e.to_string()))?;
+
+        // Emissive meshes aren't sampled, and are only found by scattered rays.
+        let emissive = matches!(
+            materials.get(object.material_name()),
+            Some(Material::DiffuseLight { .. })
+        );
+        let samplable = matches!(
+            object,
+            ObjectDesc::Sphere { .. }
+                | ObjectDesc::Triangle { .. }
+                | ObjectDesc::Quad { .. }
+                | ObjectDesc::Disk { .. }
+                | ObjectDesc::Ellipse { .. }
+        );
+        if emissive && samplable {
+            lights
+                .add(entry.place(&key, object.build(&key, &materials, base_dir)?)?)
+                .map_err(|e| invalid(&key, e.to_string()))?;
+        }
+    }
+
+    Ok(Scene {
+        world,
+        lights,
+        camera,
+    })
+}
+
+impl CameraDesc {
+    fn build(&self) -> Result<Camera, SceneError> {
+        let mut cam = Camera::new();
+
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            if !is_positive(aspect_ratio as f64) {
+                return Err(invalid("camera.aspect_ratio", "must be positive"));
+            }
+            cam.aspect_ratio = aspect_ratio;
+        }
+        if let Some(image_width) = self.image_width {
+            if image_width < 1 {
+                return Err(invalid("camera.image_width", "must be at least 1"));
+            }
+            cam.image_width = image_width;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            if samples_per_pixel < 1 {
+                return Err(invalid("camera.samples_per_pixel", "must be at least 1"));
+            }
+            cam.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            if max_depth < 1 {
+                return Err(invalid("camera.max_depth", "must be at least 1"));
+            }
+            cam.max_depth = max_depth;
+        }
+        if let Some(vfov) = self.vfov {
+            if !(vfov > 0.0 && vfov < 180.0) {
+                return Err(invalid("camera.vfov", "must be between 0 and 180 degrees"));
+            }
+            cam.vfov = vfov;
+        }
+        if let Some(defocus_angle) = self.defocus_angle {
+            if defocus_angle < 0.0 {
+                return Err(invalid("camera.defocus_angle", "must not be negative"));
+            }
+            cam.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            if !is_positive(focus_dist) {
+                return Err(invalid("camera.focus_dist", "must be positive"));
+            }
+            cam.focus_dist = focus_dist;
+        }
+
+        if let Some(background) = self.background {
+            if background.min_element() < 0.0 {
+                return Err(invalid("camera.background", "must not be negative"));
+            }
+            cam.background = Background::Color(background);
+        }
+        cam.seed = self.seed;
+
+        cam.look_from = self.look_from.unwrap_or(cam.look_from);
+        cam.look_at = self.look_at.unwrap_or(cam.look_at);
+        cam.look_up = self.look_up.unwrap_or(cam.look_up);
+
+        if cam.look_from == cam.look_at {
+            return Err(invalid(
+                "camera.look_at",
+                "must be a different point from `look_from`",
+            ));
+        }
+        if (cam.look_from - cam.look_at)
+            .cross(cam.look_up)
+            .length_squared()
+            == 0.0
+        {
+            return Err(invalid(
+                "camera.look_up",
+                "must not be parallel to the viewing direction",
+            ));
+        }
+
+        Ok(cam)
+    }
+}
+
+impl MaterialDesc {
+    fn build(&self, key: &str, base_dir: &Path) -> Result<Material, SceneError> {
+        match self {
+            MaterialDesc::Lambertian { albedo } => Ok(Material::lambertian(
+                albedo.build(&format!("{key}.albedo"), base_dir)?,
+            )),
+            MaterialDesc::Metal { albedo, fuzz } => {
+                if !(0.0..=1.0).contains(fuzz) {
+                    return Err(invalid(format!("{key}.fuzz"), "must be between 0 and 1"));
+                }
+                Ok(Material::metal(
+                    albedo.build(&format!("{key}.albedo"), base_dir)?,
+                    *fuzz,
+                ))
+            }
+            MaterialDesc::Dielectric { refraction_index } => {
+                if !is_positive(*refraction_index) {
+                    return Err(invalid(
+                        format!("{key}.refraction_index"),
+                        "must be positive",
+                    ));
+                }
+                Ok(Material::dielectric(*refraction_index))
+            }
+            MaterialDesc::Isotropic { albedo } => Ok(Material::Isotropic {
+                albedo: albedo.build(&format!("{key}.albedo"), base_dir)?,
+            }),
+            MaterialDesc::HenyeyGreenstein { albedo, g, emit } => {
+                if g.is_nan() || g.abs() >= 1.0 {
+                    return Err(invalid(
+                        format!("{key}.g"),
+                        "must be between -1 and 1, exclusive",
+                    ));
+                }
+                Ok(Material::HenyeyGreenstein {
+                    albedo: albedo.build(&format!("{key}.albedo"), base_dir)?,
+                    g: *g,
+                    emit: match emit {
+                        Some(emit) => emit.build(&format!("{key}.emit"), base_dir)?,
+                        None => DVec3::ZERO.into(),
+                    },
+                })
+            }
+            MaterialDesc::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                transmission,
+                ior,
+                clearcoat,
+                clearcoat_roughness,
+                sheen,
+            } => {
+                let defaults = Principled::default();
+                let fraction = |name: &str, value: &Option<f64>, default: f64| {
+                    let value = value.unwrap_or(default);
+                    if !(0.0..=1.0).contains(&value) {
+                        return Err(invalid(format!("{key}.{name}"), "must be between 0 and 1"));
+                    }
+                    Ok(value)
+                };
+                let ior = ior.unwrap_or(defaults.ior);
+                if !is_positive(ior) {
+                    return Err(invalid(format!("{key}.ior"), "must be positive"));
+                }
+                Ok(Principled {
+                    base_color: base_color.build(&format!("{key}.base_color"), base_dir)?,
+                    metallic: fraction("metallic", metallic, defaults.metallic)?,
+                    roughness: fraction("roughness", roughness, defaults.roughness)?,
+                    specular: fraction("specular", specular, defaults.specular)?,
+                    transmission: fraction("transmission", transmission, defaults.transmission)?,
+                    ior,
+                    clearcoat: fraction("clearcoat", clearcoat, defaults.clearcoat)?,
+                    clearcoat_roughness: fraction(
+                        "clearcoat_roughness",
+                        clearcoat_roughness,
+                        defaults.clearcoat_roughness,
+                    )?,
+                    sheen: fraction("sheen", sheen, defaults.sheen)?,
+                }
+                .into())
+            }
+            MaterialDesc::DiffuseLight { emit, two_sided } => Ok(Material::DiffuseLight {
+                emit: emit.build(&format!("{key}.emit"), base_dir)?,
+                two_sided: *two_sided,
+            }),
+        }
+    }
+}
+
+impl TextureDesc {
+    fn build(&self, key: &str, base_dir: &Path) -> Result<Texture, SceneError> {
+        match self {
+            TextureDesc::Color(color) => {
+                if color.min_element() < 0.0 {
+                    return Err(invalid(key, "must not be negative"));
+                }
+                Ok(Texture::Solid(*color))
+            }
+            TextureDesc::Texture(TextureKindDesc::Checker { size, even, odd }) => {
+                if !is_positive(*size) {
+                    return Err(invalid(format!("{key}.size"), "must be positive"));
+                }
+                Ok(Texture::checker(
+                    *size,
+                    even.build(&format!("{key}.even"), base_dir)?,
+                    odd.build(&format!("{key}.odd"), base_dir)?,
+                ))
+            }
+            TextureDesc::Texture(TextureKindDesc::Image { path, wrap, filter }) => {
+                let mut image = ImageTexture::load(base_dir.join(path)).map_err(|e| {
+                    invalid(format!("{key}.path"), format!("`{}`: {e}", path.display()))
+                })?;
+                image.wrap = *wrap;
+                image.filter = *filter;
+                Ok(Texture::Image(Arc::new(image)))
+            }
+            TextureDesc::Texture(TextureKindDesc::Noise { scale, style, seed }) => {
+                if !is_positive(*scale) {
+                    return Err(invalid(format!("{key}.scale"), "must be positive"));
+                }
+                Ok(Texture::noise(*seed, *scale, *style))
+            }
+        }
+    }
+}
+
+// The shape a medium fills. Only convex shapes work, since a ray must cross the boundary no more
+// than twice.
+#[derive(Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BoundaryDesc {
+    Sphere { center: DVec3, radius: f64 },
+    Box { a: DVec3, b: DVec3 },
+}
+
+impl ObjectEntry {
+    // Wraps the built object in its placement, if it has one.
+    fn place<T: Hittable + 'static>(
+        &self,
+        key: &str,
+        object: T,
+    ) -> Result<Box<dyn Hittable>, SceneError> {
+        if self.scale.is_none() && self.rotate_y.is_none() && self.translate.is_none() {
+            return Ok(Box::new(object));
+        }
+
+        let scale = self.scale.unwrap_or(DVec3::ONE);
+        if !(scale.is_finite() && scale.min_element().abs() > 0.0) {
+            return Err(invalid(
+                format!("{key}.scale"),
+                "every component must be finite and not zero",
+            ));
+        }
+        let rotation = DQuat::from_rotation_y(self.rotate_y.unwrap_or(0.0).to_radians());
+        let translation = self.translate.unwrap_or(DVec3::ZERO);
+        let matrix = DMat4::from_scale_rotation_translation(scale, rotation, translation);
+
+        match Transform::new(object, matrix) {
+            Some(transform) => Ok(Box::new(transform)),
+            None => Err(invalid(key, "rotate_y and translate must be finite")),
+        }
+    }
+}
+
+impl ObjectDesc {
+    fn material_name(&self) -> &str {
+        match self {
+            ObjectDesc::Sphere { material, .. }
+            | ObjectDesc::Triangle { material, .. }
+            | ObjectDesc::Quad { material, .. }
+            | ObjectDesc::Disk { material, .. }
+            | ObjectDesc::Ellipse { material, .. }
+            | ObjectDesc::Box { material, .. }
+            | ObjectDesc::Medium { material, .. }
+            | ObjectDesc::Volume { material, .. }
+            | ObjectDesc::Obj { material, .. }
+            | ObjectDesc::Stl { material, .. } => material,
+        }
+    }
+
+    fn build(
+        &self,
+        key: &str,
+        materials: &BTreeMap<&str, Material>,
+        base_dir: &Path,
+    ) -> Result<Box<dyn Hittable>, SceneError> {
+        let name = self.material_name();
+        let mat = materials.get(name).cloned().ok_or_else(|| {
+            invalid(
+                format!("{key}.material"),
+                format!("no material named `{name}` in [materials]"),
+            )
+        })?;
+
+        match self {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                velocity,
+                ..
+            } => {
+                if !is_positive(*radius) {
+                    return Err(invalid(format!("{key}.radius"), "must be positive"));
+                }
+                Ok(match velocity {
+                    Some(velocity) => Box::new(Sphere::new_moving(
+                        Ray::with_direction(*center, *velocity),
+                        *radius,
+                        mat,
+                    )),
+                    None => Box::new(Sphere::new_stationary(*center, *radius, mat)),
+                })
+            }
+            ObjectDesc::Triangle {
+                vertices, normals, ..
+            } => {
+                let [v0, v1, v2] = *vertices;
+                if (v1 - v0).cross(v2 - v0).length_squared() == 0.0 {
+                    return Err(invalid(format!("{key}.vertices"), "triangle has no area"));
+                }
+                Ok(match normals {
+                    Some(normals) => Box::new(Triangle::with_normals(v0, v1, v2, *normals, mat)),
+                    None => Box::new(Triangle::new(v0, v1, v2, mat)),
+                })
+            }
+            ObjectDesc::Quad { q, u, v, .. } => {
+                if u.cross(*v).length_squared() == 0.0 {
+                    return Err(invalid(
+                        format!("{key}.v"),
+                        "is parallel to u, so the quad has no area",
+                    ));
+                }
+                Ok(Box::new(Quad::new(*q, *u, *v, mat)))
+            }
+            ObjectDesc::Disk {
+                center,
+                normal,
+                radius,
+                ..
+            } => {
+                if !is_positive(*radius) {
+                    return Err(invalid(format!("{key}.radius"), "must be positive"));
+                }
+                if normal.length_squared() == 0.0 {
+                    return Err(invalid(format!("{key}.normal"), "must not be zero"));
+                }
+                Ok(Box::new(Quad::disk(*center, *normal, *radius, mat)))
+            }
+            ObjectDesc::Ellipse { center, u, v, .. } => {
+                if u.cross(*v).length_squared() == 0.0 {
+                    return Err(invalid(
+                        format!("{key}.v"),
+                        "is parallel to u, so the ellipse has no area",
+                    ));
+                }
+                Ok(Box::new(Quad::ellipse(*center, *u, *v, mat)))
+            }
+            ObjectDesc::Box { a, b, .. } => Ok(Box::new(build_box(key, *a, *b, mat)?)),
+            ObjectDesc::Medium {
+                boundary, density, ..
+            } => {
+                if !is_positive(*density) {
+                    return Err(invalid(format!("{key}.density"), "must be positive"));
+                }
+                if !is_phase_function(&mat) {
+                    return Err(invalid(
+                        format!("{key}.material"),
+                        format!("`{name}` must be an isotropic or henyey_greenstein material"),
+                    ));
+                }
+                let key = format!("{key}.boundary");
+                let boundary: Box<dyn Hittable> = match boundary {
+                    BoundaryDesc::Sphere { center, radius } => {
+                        if !is_positive(*radius) {
+                            return Err(invalid(format!("{key}.radius"), "must be positive"));
+                        }
+                        Box::new(Sphere::new_stationary(*center, *radius, Material::Default))
+                    }
+                    BoundaryDesc::Box { a, b } => {
+                        Box::new(build_box(&key, *a, *b, Material::Default)?)
+                    }
+                };
+                Ok(Box::new(ConstantMedium::new(boundary, *density, mat)))
+            }
+            ObjectDesc::Volume {
+                path,
+                density,
+                emission,
+                ..
+            } => {
+                if !is_positive(*density) {
+                    return Err(invalid(format!("{key}.density"), "must be positive"));
+                }
+                // Only henyey_greenstein has an emission color to scale the emission grid by.
+                if emission.is_some() && !matches!(mat, Material::HenyeyGreenstein { .. }) {
+                    return Err(invalid(
+                        format!("{key}.material"),
+                        format!("`{name}` must be a henyey_greenstein material to glow"),
+                    ));
+                }
+                if !is_phase_function(&mat) {
+                    return Err(invalid(
+                        format!("{key}.material"),
+                        format!("`{name}` must be an isotropic or henyey_greenstein material"),
+                    ));
+                }
+                let load = |field: &str, path: &PathBuf| {
+                    VoxelGrid::load(base_dir.join(path)).map_err(|e| {
+                        invalid(
+                            format!("{key}.{field}"),
+                            format!("`{}`: {e}", path.display()),
+                        )
+                    })
+                };
+                let mut volume = GridVolume::new(load("path", path)?, *density, mat);
+                if let Some(emission) = emission {
+                    volume = volume.with_emission(load("emission", emission)?);
+                }
+                Ok(Box::new(volume))
+            }
+            ObjectDesc::Obj { path, .. } => {
+                let mesh = obj::load_obj(base_dir.join(path), mat).map_err(|e| {
+                    invalid(format!("{key}.path"), format!("`{}`: {e}", path.display()))
+                })?;
+                Ok(Box::new(mesh))
+            }
+            ObjectDesc::Stl {
+                path,
+                weld_tolerance,
+                crease_angle,
+                ..
+            } => {
+                let defaults = StlOptions::default();
+                let options = StlOptions {
+                    weld_tolerance: weld_tolerance.unwrap_or(defaults.weld_tolerance),
+                    crease_angle: crease_angle.unwrap_or(defaults.crease_angle),
+                };
+                let mesh = stl::load_stl(base_dir.join(path), mat, options).map_err(|e| {
+                    invalid(format!("{key}.path"), format!("`{}`: {e}", path.display()))
+                })?;
+                Ok(Box::new(mesh))
+            }
+        }
+    }
+}
+
+// A large ground sphere, three feature spheres and a scattering of small moving ones
+pub fn default_scene(seed: Option<u64>) -> Scene {
+    // Set up rng for later, reproducibly if given a seed
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    // Create a scene to add objects to
+    let mut world = HittableList::default();
+    let mut add = |sphere: Sphere| {
+        world
+            .add(Box::new(sphere))
+            .expect("the built-in spheres all have finite bounds")
+    };
+
+    // Ground
+    add(Sphere::new_stationary(
+        DVec3::new(0.0, -1000.0, -1.0),
+        1000.0,
+        Material::lambertian(DVec3::new(0.5, 0.5, 0.5)),
+    ));
+
+    // Dielectric Ball
+    add(Sphere::new_stationary(
+        DVec3::new(0.0, 1.0, 0.0),
+        1.0,
+        Material::dielectric(1.5),
+    ));
+
+    // Lambertian Ball
+    add(Sphere::new_stationary(
+        DVec3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Material::lambertian(DVec3::new(0.4, 0.2, 0.1)),
+    ));
+
+    // Metal Ball
+    add(Sphere::new_stationary(
+        DVec3::new(4.0, 1.0, 0.0),
+        1.0,
+        Material::metal(DVec3::new(0.7, 0.6, 0.5), 0.0),
+    ));
+
+    for _ in 0..500 {
+        let spread = 6.0;
+        let vel = 1.0 / 3.0;
+
+        let center = DVec3::new(
+            (rng.gen::<f64>() - 0.5) * 2.0 * spread,
+            0.2,
+            (rng.gen::<f64>() - 0.5) * 2.0 * spread,
+        );
+
+        let speed = DVec3::new(0.0, rng.gen::<f64>() * vel, 0.0);
+        let mat = match rng.gen::<f64>() {
+            x if x < 0.33 => Material::lambertian(DVec3::new(rng.gen(), rng.gen(), rng.gen())),
+            x if x < 0.66 => Material::metal(
+                DVec3::new(rng.gen(), rng.gen(), rng.gen()),
+                rng.gen::<f64>() / 2.0,
+            ),
+            _ => Material::dielectric(1.0 / (rng.gen::<f64>() + 0.5)),
+        };
+
+        // Mini balls
+        add(Sphere::new_moving(
+            Ray::with_direction(center, speed),
+            0.15,
+            mat,
+        ));
+    }
+
+    let mut cam: Camera = Camera::new();
+
+    cam.aspect_ratio = 4.0 / 3.0;
+    cam.image_width = 320;
+    cam.samples_per_pixel = 50;
+    cam.max_depth = 100;
+
+    cam.vfov = 20.0;
+    cam.look_from = DVec3::new(13.0, 2.0, 3.0);
+    cam.look_at = DVec3::new(0.0, 0.0, 0.0);
+    cam.look_up = DVec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.6;
+    cam.focus_dist = 10.0;
+
+    // The same seed that placed the spheres also drives the render
+    cam.seed = seed;
+
+    Scene {
+        world,
+        lights: HittableList::default(),
+        camera: cam,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Interval;
+
+    fn parse(text: &str) -> Result<Scene, SceneError> {
+        parse_scene(text, Path::new("."))
+    }
+
+    fn error_message(text: &str) -> String {
+        match parse(text) {
+            Ok(_) => panic!("expected the scene to be rejected"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn example_scene_test() {
+        let scene = load_scene("scenes/three_spheres.toml").unwrap();
+
+        assert_eq!(scene.camera.image_width, 400);
+        assert_eq!(scene.camera.samples_per_pixel, 50);
+        assert_eq!(scene.camera.look_from, DVec3::new(13.0, 2.0, 3.0));
+        assert_eq!(scene.world.len(), 4);
+
+        // The ground is just below the origin, between the feature spheres.
+        let r = Ray::with_direction(DVec3::new(2.0, 10.0, 0.0), -DVec3::Y);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!(rec.p.y.abs() < 0.01);
+        let Material::Principled(bsdf) = rec.mat else {
+            panic!("expected the lambertian ground");
+        };
+        assert_eq!((bsdf.metallic, bsdf.transmission), (0.0, 0.0));
+    }
+
+    #[test]
+    fn mesh_paths_are_relative_to_the_scene_test() {
+        let scene = parse_scene(
+            r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "stl"
+            path = "cube_ascii.stl"
+            material = "white"
+            "#,
+            Path::new("tests/fixtures"),
+        )
+        .unwrap();
+
+        assert_eq!(scene.world.len(), 1);
+        let bbox = scene.world.bounding_box().unwrap();
+        assert!((bbox.x.max - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn placement_test() {
+        let scene = parse_scene(
+            r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "stl"
+            path = "cube_ascii.stl"
+            material = "white"
+            scale = [2, 1, 1]
+            translate = [10, 0, 0]
+
+            [[objects]]
+            type = "stl"
+            path = "cube_ascii.stl"
+            material = "white"
+            rotate_y = 45
+            translate = [-10, 0, 0]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "white"
+            translate = [0, 5, 0]
+            "#,
+            Path::new("tests/fixtures"),
+        )
+        .unwrap();
+        assert_eq!(scene.world.len(), 3);
+
+        let bbox = scene.world.bounding_box().unwrap();
+        assert!((bbox.y.max - 6.0).abs() < 1e-3, "{bbox:?}");
+        // The stretched cube reaches twice as far along x
+        let r = Ray::with_direction(DVec3::new(20.0, 0.5, 0.5), -DVec3::X);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!((rec.p.x - 12.0).abs() < 1e-3, "{}", rec.p);
+        // The turned cube has a corner pointing along +x, from the one at its origin
+        let r = Ray::with_direction(DVec3::new(-5.0, 0.5, 0.0), -DVec3::X);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!((rec.p.x - (2f64.sqrt() - 10.0)).abs() < 1e-3, "{}", rec.p);
+
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "m"
+            scale = [1, 0, 1]
+            "#,
+        );
+        assert!(message.contains("objects[0].scale"), "{message}");
+
+        // Placement keys don't hide typos in the object's own keys.
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "sphere"
+            centre = [0, 0, 0]
+            radius = 1
+            material = "m"
+            translate = [0, 1, 0]
+            "#,
+        );
+        assert!(message.contains("centre"), "{message}");
+    }
+
+    #[test]
+    fn textures_test() {
+        let scene = parse_scene(
+            r#"
+            [materials.floor]
+            type = "lambertian"
+            albedo = { type = "checker", size = 2, even = [1, 1, 1], odd = { type = "noise", scale = 4, style = "turbulence" } }
+
+            [materials.picture]
+            type = "metal"
+            albedo = { type = "image", path = "quad.ppm", wrap = "clamp", filter = "nearest" }
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, -2]
+            radius = 1
+            material = "picture"
+
+            [[objects]]
+            type = "sphere"
+            center = [0, -101, 0]
+            radius = 100
+            material = "floor"
+            "#,
+            Path::new("tests/fixtures"),
+        )
+        .unwrap();
+
+        // The front of the picture sphere is a quarter of the way around, halfway up, which is
+        // the left column of the image, between its rows.
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        let Material::Principled(bsdf) = rec.mat else {
+            panic!("expected the metal picture");
+        };
+        assert_eq!(bsdf.metallic, 1.0);
+        let color = bsdf.base_color.value(rec.u, rec.v, rec.p);
+        assert!(color == DVec3::X || color == DVec3::Z, "{color}");
+
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = { type = "checker", size = 0, even = [1, 1, 1], odd = [0, 0, 0] }
+            "#,
+        );
+        assert!(message.contains("materials.m.albedo.size"), "{message}");
+
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = { type = "image", path = "missing.png" }
+            "#,
+        );
+        assert!(message.contains("materials.m.albedo.path"), "{message}");
+    }
+
+    #[test]
+    fn unknown_material_test() {
+        let message = error_message(
+            r#"
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "glass"
+            "#,
+        );
+        assert!(message.contains("objects[0].material"), "{message}");
+        assert!(message.contains("glass"), "{message}");
+    }
+
+    #[test]
+    fn bad_values_point_at_the_key_test() {
+        let message = error_message("[camera]\nimage_width = 0\n");
+        assert!(message.contains("camera.image_width"), "{message}");
+
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "metal"
+            albedo = [1, 1, 1]
+            fuzz = 2.0
+            "#,
+        );
+        assert!(message.contains("materials.m.fuzz"), "{message}");
+
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "m"
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = -1
+            material = "m"
+            "#,
+        );
+        assert!(message.contains("objects[1].radius"), "{message}");
+
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "sphere"
+            center = [nan, 0, 0]
+            radius = 1
+            material = "m"
+            "#,
+        );
+        assert!(message.contains("`objects[0]`"), "{message}");
+    }
+
+    #[test]
+    fn flat_shapes_test() {
+        let scene = parse(
+            r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [materials.lamp]
+            type = "diffuse_light"
+            emit = [4, 4, 4]
+
+            [[objects]]
+            type = "quad"
+            q = [-1, -1, -5]
+            u = [2, 0, 0]
+            v = [0, 2, 0]
+            material = "white"
+
+            [[objects]]
+            type = "disk"
+            center = [0, 3, -2]
+            normal = [0, -1, 0]
+            radius = 0.5
+            material = "lamp"
+
+            [[objects]]
+            type = "ellipse"
+            center = [5, 0, 0]
+            u = [1, 0, 0]
+            v = [0, 0, 2]
+            material = "lamp"
+
+            [[objects]]
+            type = "box"
+            a = [-0.5, -0.5, -3]
+            b = [0.5, 0.5, -2]
+            material = "white"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(scene.world.len(), 4);
+        // Emissive flat shapes are sampled like spheres and triangles.
+        assert_eq!(scene.lights.len(), 2);
+
+        // The box is in front of the quad.
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-9);
+        assert!(rec.front_face);
+
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "quad"
+            q = [0, 0, 0]
+            u = [1, 0, 0]
+            v = [2, 0, 0]
+            material = "m"
+            "#,
+        );
+        assert!(message.contains("objects[0].v"), "{message}");
+
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "box"
+            a = [0, 0, 0]
+            b = [1, 0, 1]
+            material = "m"
+            "#,
+        );
+        assert!(message.contains("objects[0].b"), "{message}");
+    }
+
+    #[test]
+    fn medium_test() {
+        let scene = parse(
+            r#"
+            [materials.smoke]
+            type = "isotropic"
+            albedo = [0.5, 0.5, 0.5]
+
+            [[objects]]
+            type = "medium"
+            boundary = { type = "sphere", center = [0, 0, -3], radius = 1 }
+            density = 1000
+            material = "smoke"
+            "#,
+        )
+        .unwrap();
+
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!(rec.t > 2.0 && rec.t < 2.05, "{}", rec.t);
+        assert!(matches!(rec.mat, Material::Isotropic { .. }));
+
+        let message = error_message(
+            r#"
+            [materials.smoke]
+            type = "isotropic"
+            albedo = [0.5, 0.5, 0.5]
+
+            [[objects]]
+            type = "medium"
+            boundary = { type = "box", a = [0, 0, 0], b = [1, 1, 0] }
+            density = 1
+            material = "smoke"
+            "#,
+        );
+        assert!(message.contains("objects[0].boundary.b"), "{message}");
+
+        let message = error_message(
+            r#"
+            [materials.paint]
+            type = "lambertian"
+            albedo = [0.5, 0.5, 0.5]
+
+            [[objects]]
+            type = "medium"
+            boundary = { type = "sphere", center = [0, 0, -3], radius = 1 }
+            density = 1
+            material = "paint"
+            "#,
+        );
+        assert!(message.contains("objects[0].material"), "{message}");
+    }
+
+    #[test]
+    fn principled_test() {
+        let scene = parse(
+            r#"
+            [materials.paint]
+            type = "principled"
+            base_color = [0.7, 0.1, 0.1]
+            roughness = 0.3
+            clearcoat = 1
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, -2]
+            radius = 1
+            material = "paint"
+            "#,
+        )
+        .unwrap();
+
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        let Material::Principled(bsdf) = &rec.mat else {
+            panic!("expected a principled material");
+        };
+        assert_eq!(bsdf.roughness, 0.3);
+        assert_eq!(bsdf.clearcoat, 1.0);
+        // Everything else is the default.
+        assert_eq!(bsdf.metallic, 0.0);
+        assert_eq!(bsdf.ior, 1.5);
+
+        let message = error_message(
+            r#"
+            [materials.paint]
+            type = "principled"
+            base_color = [0.7, 0.1, 0.1]
+            metallic = 2
+            "#,
+        );
+        assert!(message.contains("materials.paint.metallic"), "{message}");
+
+        let message = error_message(
+            r#"
+            [materials.paint]
+            type = "principled"
+            base_color = [0.7, 0.1, 0.1]
+            ior = 0
+            "#,
+        );
+        assert!(message.contains("materials.paint.ior"), "{message}");
+    }
+
+    #[test]
+    fn volume_test() {
+        let volume = |material: &str, extra: &str| {
+            format!(
+                r#"
+                [materials.cloud]
+                {material}
+
+                [[objects]]
+                type = "volume"
+                path = "slab.vol"
+                material = "cloud"
+                {extra}
+                "#
+            )
+        };
+
+        // The fixture fills the unit cube with density 1, so a lot more makes it opaque.
+        let scene = parse_scene(
+            &volume(
+                r#"type = "henyey_greenstein"
+                albedo = [0.9, 0.9, 0.9]
+                g = 0.5
+                emit = [2, 2, 2]"#,
+                r#"density = 1000
+                emission = "slab.vol""#,
+            ),
+            Path::new("tests/fixtures"),
+        )
+        .unwrap();
+        let r = Ray::with_direction(DVec3::new(0.5, 0.5, 3.0), -DVec3::Z);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!(rec.t > 2.0 && rec.t < 2.05, "{}", rec.t);
+        let Material::HenyeyGreenstein { g, .. } = &rec.mat else {
+            panic!("expected the henyey_greenstein phase function");
+        };
+        assert_eq!(*g, 0.5);
+        assert_eq!(rec.mat.emitted(&r, &rec), DVec3::splat(2.0));
+        assert!(scene.lights.is_empty());
+
+        let message = parse_scene(
+            &volume(
+                r#"type = "henyey_greenstein"
+                albedo = [0.9, 0.9, 0.9]
+                g = 1"#,
+                "",
+            ),
+            Path::new("tests/fixtures"),
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(message.contains("materials.cloud.g"), "{message}");
+
+        // A volume needs a phase function, and only henyey_greenstein can glow.
+        for (material, extra) in [
+            (
+                r#"type = "lambertian"
+                albedo = [0.9, 0.9, 0.9]"#,
+                "",
+            ),
+            (
+                r#"type = "isotropic"
+                albedo = [0.9, 0.9, 0.9]"#,
+                r#"emission = "slab.vol""#,
+            ),
+        ] {
+            let message = parse_scene(&volume(material, extra), Path::new("tests/fixtures"))
+                .err()
+                .unwrap()
+                .to_string();
+            assert!(message.contains("objects[0].material"), "{message}");
+        }
+
+        let message = parse_scene(
+            &volume(
+                r#"type = "henyey_greenstein"
+                albedo = [0.9, 0.9, 0.9]
+                g = 0"#,
+                r#"emission = "cube_ascii.stl""#,
+            ),
+            Path::new("tests/fixtures"),
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(message.contains("objects[0].emission"), "{message}");
+        assert!(message.contains("cube_ascii.stl"), "{message}");
+    }
+
+    #[test]
+    fn lights_and_background_test() {
+        let scene = parse(
+            r#"
+            [camera]
+            background = [0, 0, 0]
+            seed = 7
+
+            [materials.lamp]
+            type = "diffuse_light"
+            emit = [4, 4, 4]
+            two_sided = true
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, -1]
+            radius = 0.5
+            material = "lamp"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(scene.camera.background, Background::Color(DVec3::ZERO));
+        assert_eq!(scene.camera.seed, Some(7));
+        assert_eq!(scene.lights.len(), 1);
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+        let rec = scene
+            .world
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+            .unwrap();
+        assert!(matches!(
+            rec.mat,
+            Material::DiffuseLight {
+                two_sided: true,
+                ..
+            }
+        ));
+
+        let message = error_message(
+            r#"
+            [materials.lamp]
+            type = "diffuse_light"
+            emit = [-1, 0, 0]
+            "#,
+        );
+        assert!(message.contains("materials.lamp.emit"), "{message}");
+    }
+
+    #[test]
+    fn syntax_and_type_errors_report_lines_test() {
+        // A typo in a key
+        let message = error_message("[camera]\nvfvo = 20\n");
+        assert!(message.contains("line 2"), "{message}");
+        assert!(message.contains("vfvo"), "{message}");
+
+        // A vector with too few components
+        let message = error_message("\n[camera]\nlook_at = [0, 1]\n");
+        assert!(message.contains("line 3"), "{message}");
+
+        // An unknown material type
+        let message = error_message("[materials.m]\ntype = \"plastic\"\n");
+        assert!(message.contains("plastic"), "{message}");
+    }
+
+    #[test]
+    fn missing_mesh_file_test() {
+        let message = error_message(
+            r#"
+            [materials.m]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "obj"
+            path = "does/not/exist.obj"
+            material = "m"
+            "#,
+        );
+        assert!(message.contains("objects[0].path"), "{message}");
+        assert!(message.contains("does/not/exist.obj"), "{message}");
+    }
+}
@@ -4,8 +4,12 @@ use crate::hittable::Hittable;
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::utils::{self, random_dvec3_unit};
 use glam::DVec3;
+use rand::Rng;
+use std::f64;
 
+#[derive(Clone)]
 pub struct Sphere {
     center: Ray,
     radius: f64,
@@ -42,7 +46,7 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, ray_t: Interval, debug: bool) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let current_center = self.center.at(r.time);
         let oc = current_center - r.origin;
         let a = r.direction.dot(r.direction);
@@ -51,75 +55,157 @@ impl Hittable for Sphere {
 
         let discriminant = (b * b) - (4.0 * a * c);
 
-        if debug {
-            println!("sphere::hit::r: {:?}", r);
-            println!("sphere::hit::self.center: {:?}", self.center);
-            println!("sphere::hit::self.radius: {:?}", self.radius);
-            println!("sphere::hit::a: {:?}", a);
-            println!("sphere::hit::b: {:?}", b);
-            println!("sphere::hit::c: {:?}", c);
-            println!("sphere::hit::r.time: {:?}", r.time);
-            println!("sphere::hit::current_center: {:?}", current_center);
-        }
-
         if discriminant < 0.0 {
             return None;
         }
 
         let sqrt_d = discriminant.sqrt();
 
-        if debug {
-            println!("sphere::hit::sqrt_d: {:?}", sqrt_d);
-        }
-
         // Find the nearest root that lies in the acceptable range.
         let mut root = (-b - sqrt_d) / (2.0 * a);
 
         if !ray_t.surrounds(root) {
             root = (-b + sqrt_d) / (2.0 * a);
-            if debug {
-                println!("not first {}", root)
-            }
             if !ray_t.surrounds(root) {
-                if debug {
-                    println!("not either {}", root)
-                }
                 return None;
-            } else {
-                if debug {
-                    println!("was second with {}", root)
-                }
-            }
-        } else {
-            if debug {
-                println!("was first {}", root)
             }
         }
-        if debug {
-            println!("sphere::hit::root: {:?}", root);
-        }
 
         let mut rec = HitRecord {
             p: r.at(root),
             t: root,
-            normal: DVec3::ZERO,
-            front_face: false,
-            mat: self.mat,
+            mat: &self.mat,
+            ..Default::default()
         };
 
         let outward_normal = (rec.p - current_center) / self.radius;
+        (rec.u, rec.v) = sphere_uv(outward_normal);
 
         rec.set_face_normal(r, outward_normal);
 
-        if debug {
-            println!("sphere::hit::rec: {:?}", rec);
-        }
         Some(rec)
     }
 
+    // Lights are sampled where they are at the start of the shutter interval.
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        let ray = Ray::with_direction(origin, direction);
+        if self
+            .hit(&ray, Interval::new(0.001, f64::INFINITY))
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center.origin - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // Inside the sphere there's no cone to sample.
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2.0 * f64::consts::PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    // Samples the cone of directions the sphere covers, uniformly by solid angle.
+    fn random(&self, origin: DVec3) -> DVec3 {
+        let direction = self.center.origin - origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return random_dvec3_unit();
+        }
+
+        let mut rng = utils::rng();
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * f64::consts::PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        let w = direction.normalize();
+        let (u, v) = w.any_orthonormal_pair();
+        u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * z
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
         Some(self.bbox)
     }
 }
 
 // TODO test hit function
+
+// Surface coordinates of a point on the unit sphere. u goes once around the y axis starting from
+// -x, and v runs from the bottom pole at 0 to the top pole at 1.
+fn sphere_uv(p: DVec3) -> (f64, f64) {
+    let theta = (-p.y).acos();
+    let phi = (-p.z).atan2(p.x) + f64::consts::PI;
+    (phi / (2.0 * f64::consts::PI), theta / f64::consts::PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light() -> Sphere {
+        Sphere::new_stationary(
+            DVec3::new(0.0, 0.0, -2.0),
+            1.0,
+            Material::DiffuseLight {
+                emit: DVec3::ONE.into(),
+                two_sided: false,
+            },
+        )
+    }
+
+    #[test]
+    fn light_pdf_test() {
+        let sphere = light();
+        let origin = DVec3::ZERO;
+
+        // Directions are picked uniformly from the cone the sphere covers, which spans 30 degrees.
+        let solid_angle = 2.0 * f64::consts::PI * (1.0 - 30f64.to_radians().cos());
+        for _ in 0..100 {
+            let direction = sphere.random(origin);
+            let pdf = sphere.pdf_value(origin, direction);
+            assert!((pdf - 1.0 / solid_angle).abs() < 1e-9, "{pdf}");
+        }
+        assert_eq!(sphere.pdf_value(origin, DVec3::Z), 0.0);
+
+        // The density integrates to one over the sphere of directions.
+        let samples = 200_000;
+        let total: f64 = (0..samples)
+            .map(|_| sphere.pdf_value(origin, random_dvec3_unit()))
+            .sum();
+        let integral = total / samples as f64 * 4.0 * f64::consts::PI;
+        assert!((integral - 1.0).abs() < 0.03, "{integral}");
+    }
+
+    #[test]
+    fn sphere_uv_test() {
+        let check = |p: DVec3, u: f64, v: f64| {
+            let (hit_u, hit_v) = sphere_uv(p);
+            assert!(
+                (hit_u - u).abs() < 1e-12 && (hit_v - v).abs() < 1e-12,
+                "{p}"
+            );
+        };
+        check(DVec3::X, 0.5, 0.5);
+        check(DVec3::Y, 0.5, 1.0);
+        check(DVec3::Z, 0.25, 0.5);
+        check(DVec3::NEG_X, 0.0, 0.5);
+        check(DVec3::NEG_Y, 0.5, 0.0);
+        check(DVec3::NEG_Z, 0.75, 0.5);
+    }
+
+    #[test]
+    fn hit_records_uv_test() {
+        let sphere = light();
+        // Straight at the front of the sphere, which faces +z
+        let r = Ray::with_direction(DVec3::ZERO, -DVec3::Z);
+        let rec = sphere.hit(&r, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-12);
+        assert!((rec.v - 0.5).abs() < 1e-12);
+    }
+}
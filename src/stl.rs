@@ -0,0 +1,487 @@
+// STL parsing, for both the ASCII and binary encodings.
+//
+// STL files store every facet as three independent vertices, so shared edges aren't recorded
+// anywhere. After reading, vertices closer than a tolerance are welded back together, which
+// recovers the connectivity needed to smooth normals across neighboring facets.
+
+use crate::material::Material;
+use crate::mesh::{MeshData, MeshFace, TriangleMesh};
+use glam::DVec3;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(std::io::Error),
+    Ascii { line: usize, message: String },
+    Binary(String),
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StlError::Io(e) => write!(f, "failed to read STL: {e}"),
+            StlError::Ascii { line, message } => write!(f, "STL line {line}: {message}"),
+            StlError::Binary(message) => write!(f, "binary STL: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for StlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StlError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for StlError {
+    fn from(e: std::io::Error) -> StlError {
+        StlError::Io(e)
+    }
+}
+
+// One facet as stored in the file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Facet {
+    pub normal: DVec3,
+    pub vertices: [DVec3; 3],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StlOptions {
+    // Vertices closer together than this are merged into one.
+    pub weld_tolerance: f64,
+    // Normals are smoothed across edges where neighboring facets meet at less than this angle, in
+    // degrees. Sharper edges, like the corners of a machined part, stay crisp. Zero gives flat
+    // shading everywhere.
+    pub crease_angle: f64,
+}
+
+impl Default for StlOptions {
+    fn default() -> StlOptions {
+        StlOptions {
+            weld_tolerance: 1e-5,
+            crease_angle: 40.0,
+        }
+    }
+}
+
+const BINARY_HEADER_LEN: usize = 80;
+const BINARY_FACET_LEN: usize = 50;
+
+pub fn load_stl(
+    path: impl AsRef<Path>,
+    mat: Material,
+    options: StlOptions,
+) -> Result<TriangleMesh, StlError> {
+    let bytes = std::fs::read(path)?;
+    let facets = parse_stl(&bytes)?;
+    Ok(TriangleMesh::new(weld(&facets, options), mat))
+}
+
+// Reads the facets of an STL file in either encoding.
+pub fn parse_stl(bytes: &[u8]) -> Result<Vec<Facet>, StlError> {
+    if is_binary(bytes) {
+        parse_binary(bytes)
+    } else if bytes.trim_ascii_start().starts_with(b"solid") {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|e| StlError::Binary(format!("not valid ASCII or binary STL: {e}")))?;
+        parse_ascii(text)
+    } else {
+        // Without the `solid` keyword it can only be a (broken) binary file.
+        parse_binary(bytes)
+    }
+}
+
+// Binary files are allowed to start with `solid` too, so the only reliable test is whether the
+// facet count in the header accounts for the exact length of the file.
+fn is_binary(bytes: &[u8]) -> bool {
+    match binary_facet_count(bytes) {
+        Some(count) => bytes.len() == BINARY_HEADER_LEN + 4 + count * BINARY_FACET_LEN,
+        None => false,
+    }
+}
+
+fn binary_facet_count(bytes: &[u8]) -> Option<usize> {
+    let count = bytes.get(BINARY_HEADER_LEN..BINARY_HEADER_LEN + 4)?;
+    Some(u32::from_le_bytes(count.try_into().unwrap()) as usize)
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Facet>, StlError> {
+    let count = binary_facet_count(bytes)
+        .ok_or_else(|| StlError::Binary("file is too short for an STL header".to_string()))?;
+
+    let body = &bytes[BINARY_HEADER_LEN + 4..];
+    if body.len() < count * BINARY_FACET_LEN {
+        return Err(StlError::Binary(format!(
+            "header promises {count} facets, but the file only holds {}",
+            body.len() / BINARY_FACET_LEN
+        )));
+    }
+
+    let read_vec = |chunk: &[u8]| {
+        let f = |i: usize| f32::from_le_bytes(chunk[4 * i..4 * i + 4].try_into().unwrap()) as f64;
+        DVec3::new(f(0), f(1), f(2))
+    };
+
+    Ok(body
+        .chunks_exact(BINARY_FACET_LEN)
+        .take(count)
+        .map(|chunk| Facet {
+            normal: read_vec(&chunk[0..12]),
+            vertices: [
+                read_vec(&chunk[12..24]),
+                read_vec(&chunk[24..36]),
+                read_vec(&chunk[36..48]),
+            ],
+            // The trailing two byte "attribute byte count" has no standard meaning.
+        })
+        .collect())
+}
+
+fn parse_ascii(text: &str) -> Result<Vec<Facet>, StlError> {
+    let mut facets = Vec::new();
+    let mut tokens = AsciiTokens::new(text);
+
+    tokens.expect("solid")?;
+    // The solid's name runs to the end of the line, and may be empty.
+    tokens.skip_line();
+
+    loop {
+        let (line, word) = tokens.next_word()?;
+        match word {
+            "facet" => {
+                tokens.expect("normal")?;
+                let normal = tokens.vector()?;
+                tokens.expect("outer")?;
+                tokens.expect("loop")?;
+                let mut vertices = [DVec3::ZERO; 3];
+                for v in vertices.iter_mut() {
+                    tokens.expect("vertex")?;
+                    *v = tokens.vector()?;
+                }
+                tokens.expect("endloop")?;
+                tokens.expect("endfacet")?;
+                facets.push(Facet { normal, vertices });
+            }
+            "endsolid" => return Ok(facets),
+            other => {
+                return Err(StlError::Ascii {
+                    line,
+                    message: format!("expected `facet` or `endsolid`, found `{other}`"),
+                })
+            }
+        }
+    }
+}
+
+// Whitespace separated words of an ASCII STL, tagged with their line numbers.
+struct AsciiTokens<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    words: std::str::SplitWhitespace<'a>,
+    line: usize,
+}
+
+impl<'a> AsciiTokens<'a> {
+    fn new(text: &'a str) -> AsciiTokens<'a> {
+        AsciiTokens {
+            lines: text.lines().enumerate(),
+            words: "".split_whitespace(),
+            line: 0,
+        }
+    }
+
+    fn next_word(&mut self) -> Result<(usize, &'a str), StlError> {
+        loop {
+            if let Some(word) = self.words.next() {
+                return Ok((self.line, word));
+            }
+            let Some((i, line)) = self.lines.next() else {
+                return Err(StlError::Ascii {
+                    line: self.line,
+                    message: "unexpected end of file".to_string(),
+                });
+            };
+            self.line = i + 1;
+            self.words = line.split_whitespace();
+        }
+    }
+
+    fn skip_line(&mut self) {
+        self.words = "".split_whitespace();
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), StlError> {
+        let (line, word) = self.next_word()?;
+        if word == keyword {
+            Ok(())
+        } else {
+            Err(StlError::Ascii {
+                line,
+                message: format!("expected `{keyword}`, found `{word}`"),
+            })
+        }
+    }
+
+    fn vector(&mut self) -> Result<DVec3, StlError> {
+        let mut v = DVec3::ZERO;
+        for i in 0..3 {
+            let (line, word) = self.next_word()?;
+            v[i] = word.parse().map_err(|_| StlError::Ascii {
+                line,
+                message: format!("expected a number, found `{word}`"),
+            })?;
+        }
+        Ok(v)
+    }
+}
+
+// Merges coincident vertices and builds an indexed mesh with per-corner normals.
+pub fn weld(facets: &[Facet], options: StlOptions) -> MeshData {
+    let mut data = MeshData::default();
+    let mut grid = WeldGrid::new(options.weld_tolerance);
+
+    // Facet normals in files are frequently missing or stale, so the winding is the source of
+    // truth for geometry. Facet normals are only used to fix up triangles wound the wrong way.
+    let mut face_normals = Vec::with_capacity(facets.len());
+    for facet in facets {
+        let mut vertices = facet.vertices;
+        let mut cross = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]);
+        if cross.dot(facet.normal) < 0.0 {
+            vertices.swap(1, 2);
+            cross = -cross;
+        }
+
+        let positions = vertices.map(|v| grid.insert(v, &mut data.positions));
+
+        // Facets that collapse to a line or a point once welded can never be hit.
+        if positions[0] == positions[1]
+            || positions[1] == positions[2]
+            || positions[2] == positions[0]
+        {
+            continue;
+        }
+
+        data.faces.push(MeshFace {
+            positions,
+            normals: None,
+            texcoords: None,
+        });
+        face_normals.push(cross);
+    }
+
+    if options.crease_angle > 0.0 {
+        smooth_normals(&mut data, &face_normals, options.crease_angle);
+    }
+
+    data
+}
+
+// Gives every face corner a normal averaged over the faces around that vertex which meet the
+// corner's own face at less than the crease angle. Each face is weighted by its angle at the
+// vertex, so the result doesn't depend on how the surface happens to be triangulated.
+fn smooth_normals(data: &mut MeshData, face_normals: &[DVec3], crease_angle: f64) {
+    let cos_crease = crease_angle.to_radians().cos();
+    let face_normals: Vec<DVec3> = face_normals.iter().map(|n| n.normalize()).collect();
+
+    // For every vertex, the faces touching it and their angle there.
+    let mut faces_at_vertex = vec![Vec::new(); data.positions.len()];
+    for (f, face) in data.faces.iter().enumerate() {
+        for corner in 0..3 {
+            let p = face.positions[corner];
+            let a = data.positions[face.positions[(corner + 1) % 3]] - data.positions[p];
+            let b = data.positions[face.positions[(corner + 2) % 3]] - data.positions[p];
+            faces_at_vertex[p].push((f, a.angle_between(b)));
+        }
+    }
+
+    for f in 0..data.faces.len() {
+        let own = face_normals[f];
+        let mut corner_normals = [0; 3];
+
+        for (corner, &p) in data.faces[f].positions.iter().enumerate() {
+            let sum: DVec3 = faces_at_vertex[p]
+                .iter()
+                .filter(|&&(g, _)| face_normals[g].dot(own) >= cos_crease)
+                .map(|&(g, angle)| angle * face_normals[g])
+                .sum();
+            corner_normals[corner] = data.normals.len();
+            data.normals.push(sum.normalize());
+        }
+
+        data.faces[f].normals = Some(corner_normals);
+    }
+}
+
+// Spatial hash used to find existing vertices within the weld tolerance of a new one.
+struct WeldGrid {
+    tolerance: f64,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+}
+
+impl WeldGrid {
+    fn new(tolerance: f64) -> WeldGrid {
+        WeldGrid {
+            tolerance,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, p: DVec3) -> [i64; 3] {
+        if self.tolerance > 0.0 {
+            (p / self.tolerance).floor().as_i64vec3().to_array()
+        } else {
+            // No tolerance means only bit-identical positions are merged.
+            [
+                p.x.to_bits() as i64,
+                p.y.to_bits() as i64,
+                p.z.to_bits() as i64,
+            ]
+        }
+    }
+
+    // Returns the index of a vertex within tolerance of `p`, adding `p` if there is none.
+    fn insert(&mut self, p: DVec3, positions: &mut Vec<DVec3>) -> usize {
+        let [x, y, z] = self.cell(p);
+
+        if self.tolerance > 0.0 {
+            // With cells as wide as the tolerance, any match is in this cell or a neighbor.
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(candidates) = self.cells.get(&[x + dx, y + dy, z + dz]) {
+                            for &i in candidates {
+                                if positions[i].distance(p) <= self.tolerance {
+                                    return i;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        } else if let Some(candidates) = self.cells.get(&[x, y, z]) {
+            return candidates[0];
+        }
+
+        let i = positions.len();
+        positions.push(p);
+        self.cells.entry([x, y, z]).or_default().push(i);
+        i
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    const CUBE_ASCII: &[u8] = include_bytes!("../tests/fixtures/cube_ascii.stl");
+    const CUBE_BINARY: &[u8] = include_bytes!("../tests/fixtures/cube_binary.stl");
+
+    #[test]
+    fn ascii_and_binary_agree_test() {
+        // The binary fixture's header starts with "solid", like many CAD exports.
+        assert!(CUBE_BINARY.starts_with(b"solid"));
+
+        let ascii = parse_stl(CUBE_ASCII).unwrap();
+        let binary = parse_stl(CUBE_BINARY).unwrap();
+
+        assert_eq!(ascii.len(), 12);
+        assert_eq!(ascii, binary);
+        assert_eq!(ascii[0].normal, DVec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn weld_rebuilds_connectivity_test() {
+        let facets = parse_stl(CUBE_BINARY).unwrap();
+        let data = weld(&facets, StlOptions::default());
+
+        assert_eq!(data.positions.len(), 8);
+        assert_eq!(data.faces.len(), 12);
+
+        // Vertices that are slightly off still weld within tolerance.
+        let mut jittered = facets.clone();
+        jittered[3].vertices[0] += DVec3::splat(1e-7);
+        assert_eq!(weld(&jittered, StlOptions::default()).positions.len(), 8);
+
+        // But not with a tolerance of zero.
+        let exact = StlOptions {
+            weld_tolerance: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(weld(&jittered, exact).positions.len(), 9);
+    }
+
+    #[test]
+    fn crease_angle_test() {
+        let facets = parse_stl(CUBE_ASCII).unwrap();
+
+        // The cube's edges are all 90 degrees, so they stay sharp by default.
+        let data = weld(&facets, StlOptions::default());
+        for (face, facet) in data.faces.iter().zip(facets.iter()) {
+            for &n in &face.normals.unwrap() {
+                assert!((data.normals[n] - facet.normal).length() < 1e-12);
+            }
+        }
+
+        // Above 90 degrees, every corner normal points out diagonally.
+        let smooth = StlOptions {
+            crease_angle: 100.0,
+            ..Default::default()
+        };
+        let data = weld(&facets, smooth);
+        for face in data.faces.iter() {
+            for (&p, &n) in face.positions.iter().zip(face.normals.unwrap().iter()) {
+                let outward = (data.positions[p] - DVec3::splat(0.5)).normalize();
+                assert!(data.normals[n].dot(outward) > 0.99);
+            }
+        }
+    }
+
+    #[test]
+    fn wrongly_wound_facet_is_flipped_test() {
+        let mut facets = parse_stl(CUBE_ASCII).unwrap();
+        facets[0].vertices.swap(0, 1);
+
+        let mesh = TriangleMesh::new(
+            weld(&facets, StlOptions::default()),
+            Material::lambertian(DVec3::ONE),
+        );
+
+        // A ray from below hits the bottom face from the outside.
+        let r = Ray::with_direction(DVec3::new(0.75, 0.25, -1.0), DVec3::Z);
+        let rec = mesh
+            .hit(&r, Interval::new(f64::EPSILON, f64::INFINITY))
+            .unwrap();
+        assert!(rec.front_face);
+        assert!((rec.t - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn malformed_ascii_reports_line_test() {
+        let text = "solid broken\n  facet normal 0 0 1\n    outer loop\n      vertex 0 0 zero\n";
+        match parse_stl(text.as_bytes()) {
+            Err(StlError::Ascii { line, .. }) => assert_eq!(line, 4),
+            other => panic!("expected an ASCII error, got {other:?}"),
+        }
+
+        match parse_stl(b"solid truncated\n  facet normal 0 0 1\n") {
+            Err(StlError::Ascii { line, .. }) => assert_eq!(line, 2),
+            other => panic!("expected an ASCII error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn truncated_binary_test() {
+        let truncated = &CUBE_BINARY[..CUBE_BINARY.len() - 10];
+        // No longer a consistent binary file, and not ASCII either past the header.
+        assert!(parse_stl(truncated).is_err());
+
+        assert!(matches!(parse_stl(b"\0\0\0"), Err(StlError::Binary(_))));
+    }
+}
@@ -0,0 +1,547 @@
+// Colors that vary over a surface, looked up by the surface coordinates and position of a hit.
+
+use crate::perlin::Perlin;
+use crate::volume::VoxelGrid;
+use glam::DVec3;
+use serde::Deserialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub enum Texture {
+    Solid(DVec3),
+    // Alternates between two textures in a 3D grid of cubes `size` wide
+    Checker {
+        size: f64,
+        even: Arc<Texture>,
+        odd: Arc<Texture>,
+    },
+    Image(Arc<ImageTexture>),
+    // Gray Perlin noise, with features about 1 / `scale` across
+    Noise {
+        perlin: Arc<Perlin>,
+        scale: f64,
+        style: NoiseStyle,
+    },
+    // Another texture scaled, point by point, by the values of a voxel grid
+    Grid {
+        grid: Arc<VoxelGrid>,
+        texture: Arc<Texture>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseStyle {
+    // A single octave of smooth noise
+    Smooth,
+    // Several octaves summed together
+    Turbulence,
+    // Stripes along z, distorted by turbulence
+    #[default]
+    Marble,
+}
+
+// Octaves summed by the turbulent noise styles
+const TURBULENCE_DEPTH: u32 = 7;
+
+impl Default for Texture {
+    fn default() -> Texture {
+        Texture::Solid(DVec3::ZERO)
+    }
+}
+
+impl From<DVec3> for Texture {
+    fn from(color: DVec3) -> Texture {
+        Texture::Solid(color)
+    }
+}
+
+impl Texture {
+    pub fn checker(size: f64, even: impl Into<Texture>, odd: impl Into<Texture>) -> Texture {
+        Texture::Checker {
+            size,
+            even: Arc::new(even.into()),
+            odd: Arc::new(odd.into()),
+        }
+    }
+
+    pub fn noise(seed: u64, scale: f64, style: NoiseStyle) -> Texture {
+        Texture::Noise {
+            perlin: Arc::new(Perlin::new(seed)),
+            scale,
+            style,
+        }
+    }
+
+    // The color at surface coordinates (u, v) and position p of a hit.
+    pub fn value(&self, u: f64, v: f64, p: DVec3) -> DVec3 {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Checker { size, even, odd } => {
+                let cell = (p / *size).floor();
+                if (cell.x + cell.y + cell.z).rem_euclid(2.0) == 0.0 {
+                    even.value(u, v, p)
+                } else {
+                    odd.value(u, v, p)
+                }
+            }
+            Texture::Image(image) => image.value(u, v),
+            Texture::Noise {
+                perlin,
+                scale,
+                style,
+            } => {
+                let gray = match style {
+                    NoiseStyle::Smooth => 0.5 * (1.0 + perlin.noise(*scale * p)),
+                    NoiseStyle::Turbulence => perlin.turbulence(*scale * p, TURBULENCE_DEPTH),
+                    // The scale sets the stripe frequency, leaving the distortion unscaled.
+                    NoiseStyle::Marble => {
+                        let phase = *scale * p.z + 10.0 * perlin.turbulence(p, TURBULENCE_DEPTH);
+                        0.5 * (1.0 + phase.sin())
+                    }
+                };
+                DVec3::splat(gray)
+            }
+            Texture::Grid { grid, texture } => texture.value(u, v, p) * grid.value_at(p),
+        }
+    }
+}
+
+// How lookups outside [0, 1] are brought back onto the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    // Tile the image
+    #[default]
+    Repeat,
+    // Tile the image, flipping every other copy so the edges meet
+    Mirror,
+    // Stretch the edge pixels outward
+    Clamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    Nearest,
+    // Blend the four nearest pixels
+    #[default]
+    Bilinear,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    // Linear colors in row-major order from the top left
+    pixels: Vec<DVec3>,
+    pub wrap: WrapMode,
+    pub filter: Filter,
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(io::Error),
+    Png(png::DecodingError),
+    Format(String),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "{e}"),
+            TextureError::Png(e) => write!(f, "invalid PNG: {e}"),
+            TextureError::Format(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<io::Error> for TextureError {
+    fn from(e: io::Error) -> TextureError {
+        TextureError::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for TextureError {
+    fn from(e: png::DecodingError) -> TextureError {
+        match e {
+            png::DecodingError::IoError(e) => TextureError::Io(e),
+            e => TextureError::Png(e),
+        }
+    }
+}
+
+fn format_error(message: impl Into<String>) -> TextureError {
+    TextureError::Format(message.into())
+}
+
+impl ImageTexture {
+    // An image from linear colors in row-major order from the top left.
+    pub fn new(width: usize, height: usize, pixels: Vec<DVec3>) -> ImageTexture {
+        assert!(width > 0 && height > 0, "an image texture can't be empty");
+        assert_eq!(pixels.len(), width * height);
+        ImageTexture {
+            width,
+            height,
+            pixels,
+            wrap: WrapMode::default(),
+            filter: Filter::default(),
+        }
+    }
+
+    // Loads a PNG or PPM file, picked by its extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<ImageTexture, TextureError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let reader = BufReader::new(File::open(path)?);
+
+        match extension.as_deref() {
+            Some("png") => ImageTexture::read_png(reader),
+            Some("ppm") => ImageTexture::read_ppm(reader),
+            _ => Err(format_error(
+                "unknown image format; use a .png or .ppm file",
+            )),
+        }
+    }
+
+    pub fn read_png(reader: impl Read) -> Result<ImageTexture, TextureError> {
+        let mut decoder = png::Decoder::new(reader);
+        // Expand palettes and low bit depths to 8 bits, but keep 16 bit samples.
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        buf.truncate(info.buffer_size());
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed => unreachable!("palettes are expanded"),
+        };
+        let samples: Vec<f64> = match info.bit_depth {
+            png::BitDepth::Sixteen => buf
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64 / 65535.0)
+                .collect(),
+            _ => buf.iter().map(|&b| b as f64 / 255.0).collect(),
+        };
+
+        // Alpha is ignored.
+        let pixels = samples
+            .chunks_exact(channels)
+            .map(|c| match channels {
+                1 | 2 => DVec3::splat(c[0]),
+                _ => DVec3::new(c[0], c[1], c[2]),
+            })
+            .map(gamma_to_linear)
+            .collect();
+
+        Ok(ImageTexture::new(
+            info.width as usize,
+            info.height as usize,
+            pixels,
+        ))
+    }
+
+    // Reads a plain (P3) or raw (P6) PPM.
+    pub fn read_ppm(reader: impl BufRead) -> Result<ImageTexture, TextureError> {
+        let mut reader = reader;
+
+        let magic = ppm_token(&mut reader)?;
+        let raw = match magic.as_str() {
+            "P3" => false,
+            "P6" => true,
+            _ => return Err(format_error(format!("`{magic}` isn't a P3 or P6 PPM"))),
+        };
+        let width = ppm_number(&mut reader, "width")?;
+        let height = ppm_number(&mut reader, "height")?;
+        let max_value = ppm_number(&mut reader, "maximum value")?;
+        if width == 0 || height == 0 {
+            return Err(format_error("the image is empty"));
+        }
+        if max_value == 0 || max_value > 65535 {
+            return Err(format_error(
+                "the maximum value must be between 1 and 65535",
+            ));
+        }
+
+        let too_large = || format_error(format!("a {width}x{height} image is too large"));
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(too_large)?;
+        let samples: Vec<usize> = if raw {
+            // A single whitespace byte separates the header from the samples, and was consumed
+            // along with the maximum value.
+            let wide = max_value > 255;
+            let size = count
+                .checked_mul(if wide { 2 } else { 1 })
+                .ok_or_else(too_large)?;
+            // Read only what's there rather than trusting the header with the allocation.
+            let mut data = Vec::new();
+            reader.by_ref().take(size as u64).read_to_end(&mut data)?;
+            if data.len() != size {
+                return Err(format_error("the pixel data is cut short"));
+            }
+            if wide {
+                data.chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                    .collect()
+            } else {
+                data.into_iter().map(usize::from).collect()
+            }
+        } else {
+            (0..count)
+                .map(|_| ppm_number(&mut reader, "sample"))
+                .collect::<Result<_, _>>()?
+        };
+
+        let scale = 1.0 / max_value as f64;
+        let pixels = samples
+            .chunks_exact(3)
+            .map(|c| {
+                let c = DVec3::new(c[0] as f64, c[1] as f64, c[2] as f64) * scale;
+                gamma_to_linear(c.min(DVec3::ONE))
+            })
+            .collect();
+
+        Ok(ImageTexture::new(width, height, pixels))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // The color at texture coordinates (u, v), where v runs from the bottom of the image up.
+    pub fn value(&self, u: f64, v: f64) -> DVec3 {
+        // Continuous pixel coordinates, with pixel centers at half integers
+        let x = u * self.width as f64;
+        let y = (1.0 - v) * self.height as f64;
+
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = self.texel(x0, y0).lerp(self.texel(x0 + 1, y0), tx);
+                let bottom = self.texel(x0, y0 + 1).lerp(self.texel(x0 + 1, y0 + 1), tx);
+                top.lerp(bottom, ty)
+            }
+        }
+    }
+
+    // The pixel at integer coordinates, wrapped onto the image.
+    fn texel(&self, x: i64, y: i64) -> DVec3 {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl WrapMode {
+    fn apply(&self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+            WrapMode::Clamp => i.clamp(0, size - 1),
+        };
+        wrapped as usize
+    }
+}
+
+// Undoes the gamma of 2 that `output::linear_to_gamma` applies, so a texture saved from a render
+// reads back as the same linear colors.
+fn gamma_to_linear(color: DVec3) -> DVec3 {
+    color * color
+}
+
+// Reads the next token of a PPM header or plain sample list. Tokens are whitespace separated, and
+// `#` starts a comment running to the end of the line.
+fn ppm_token(reader: &mut impl BufRead) -> Result<String, TextureError> {
+    let mut token = String::new();
+    let mut in_comment = false;
+
+    loop {
+        let mut byte = [0];
+        if reader.read(&mut byte)? == 0 {
+            break;
+        }
+        let c = byte[0] as char;
+
+        if in_comment {
+            in_comment = c != '\n';
+        } else if c == '#' {
+            in_comment = true;
+        } else if c.is_ascii_whitespace() {
+            if !token.is_empty() {
+                break;
+            }
+        } else {
+            token.push(c);
+        }
+    }
+
+    if token.is_empty() {
+        return Err(format_error("the file ends too early"));
+    }
+    Ok(token)
+}
+
+fn ppm_number(reader: &mut impl BufRead, what: &str) -> Result<usize, TextureError> {
+    let token = ppm_token(reader)?;
+    token
+        .parse()
+        .map_err(|_| format_error(format!("expected the {what}, found `{token}`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Image;
+    use crate::output::OutputFormat;
+    use std::io::Cursor;
+
+    // A 2x2 image: red, green on top, blue, white below.
+    fn quad() -> ImageTexture {
+        ImageTexture::new(2, 2, vec![DVec3::X, DVec3::Y, DVec3::Z, DVec3::ONE])
+    }
+
+    #[test]
+    fn solid_and_checker_test() {
+        let solid = Texture::from(DVec3::new(0.1, 0.2, 0.3));
+        assert_eq!(solid.value(0.7, 0.2, DVec3::ONE), DVec3::new(0.1, 0.2, 0.3));
+
+        let checker = Texture::checker(1.0, DVec3::ONE, DVec3::ZERO);
+        assert_eq!(checker.value(0.0, 0.0, DVec3::splat(0.5)), DVec3::ONE);
+        assert_eq!(
+            checker.value(0.0, 0.0, DVec3::new(1.5, 0.5, 0.5)),
+            DVec3::ZERO
+        );
+        assert_eq!(
+            checker.value(0.0, 0.0, DVec3::new(1.5, -0.5, 0.5)),
+            DVec3::ONE
+        );
+        // Cells are the same on both sides of the origin, with no doubled row at zero.
+        assert_eq!(
+            checker.value(0.0, 0.0, DVec3::new(-0.5, 0.5, 0.5)),
+            DVec3::ZERO
+        );
+    }
+
+    #[test]
+    fn nearest_and_bilinear_test() {
+        let mut image = quad();
+        image.filter = Filter::Nearest;
+        // v runs up from the bottom of the image.
+        assert_eq!(image.value(0.25, 0.75), DVec3::X);
+        assert_eq!(image.value(0.75, 0.75), DVec3::Y);
+        assert_eq!(image.value(0.25, 0.25), DVec3::Z);
+
+        image.filter = Filter::Bilinear;
+        image.wrap = WrapMode::Clamp;
+        // Pixel centers return the pixel exactly.
+        assert_eq!(image.value(0.25, 0.75), DVec3::X);
+        // The middle of the image blends all four.
+        assert!(image
+            .value(0.5, 0.5)
+            .abs_diff_eq(DVec3::new(0.5, 0.5, 0.5), 1e-12));
+        // Halfway between the two top pixels
+        assert!(image
+            .value(0.5, 0.75)
+            .abs_diff_eq(DVec3::new(0.5, 0.5, 0.0), 1e-12));
+    }
+
+    #[test]
+    fn wrap_modes_test() {
+        let mut image = quad();
+        image.filter = Filter::Nearest;
+
+        image.wrap = WrapMode::Repeat;
+        assert_eq!(image.value(1.25, 0.75), DVec3::X);
+        assert_eq!(image.value(-0.25, 0.75), DVec3::Y);
+
+        image.wrap = WrapMode::Mirror;
+        assert_eq!(image.value(1.25, 0.75), DVec3::Y);
+        assert_eq!(image.value(-0.25, 0.75), DVec3::X);
+
+        image.wrap = WrapMode::Clamp;
+        assert_eq!(image.value(5.0, 0.75), DVec3::Y);
+        assert_eq!(image.value(-5.0, -5.0), DVec3::Z);
+    }
+
+    #[test]
+    fn read_ppm_test() {
+        let plain = "P3\n# a comment\n2 1\n255\n255 0 0   0 0 255\n";
+        let image = ImageTexture::read_ppm(Cursor::new(plain)).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.pixels, vec![DVec3::X, DVec3::Z]);
+
+        let mut raw = b"P6 1 1 65535\n".to_vec();
+        raw.extend([0x80, 0x00, 0xff, 0xff, 0x00, 0x00]);
+        let image = ImageTexture::read_ppm(Cursor::new(raw)).unwrap();
+        let expected = gamma_to_linear(DVec3::new(32768.0 / 65535.0, 1.0, 0.0));
+        assert!(image.pixels[0].abs_diff_eq(expected, 1e-12));
+
+        assert!(ImageTexture::read_ppm(Cursor::new("P5 1 1 255\n\0")).is_err());
+        assert!(ImageTexture::read_ppm(Cursor::new("P6 2 2 255\n\0\0\0")).is_err());
+        let huge = format!("P6 {} 2 255\n\0", usize::MAX / 2);
+        assert!(ImageTexture::read_ppm(Cursor::new(huge)).is_err());
+    }
+
+    #[test]
+    fn png_round_trip_test() {
+        let colors = vec![DVec3::ZERO, DVec3::splat(0.25), DVec3::X, DVec3::ONE];
+        let mut png = Vec::new();
+        Image::from_pixels(2, 2, colors.clone())
+            .encode(&mut png, OutputFormat::Png16)
+            .unwrap();
+
+        let image = ImageTexture::read_png(Cursor::new(png)).unwrap();
+        for (read, written) in image.pixels.iter().zip(colors) {
+            assert!(read.abs_diff_eq(written, 1e-4), "{read} != {written}");
+        }
+    }
+
+    #[test]
+    fn noise_texture_test() {
+        for style in [
+            NoiseStyle::Smooth,
+            NoiseStyle::Turbulence,
+            NoiseStyle::Marble,
+        ] {
+            let texture = Texture::noise(1, 4.0, style);
+            for i in 0..100 {
+                let p = DVec3::new(i as f64 * 0.31, i as f64 * 0.17, 0.5);
+                let color = texture.value(0.0, 0.0, p);
+                assert!(color.min_element() >= 0.0, "{style:?}: {color}");
+                assert_eq!(color.x, color.y);
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+// Splitting the frame into tiles, and the order workers pick them up in.
+
+pub const DEFAULT_TILE_SIZE: usize = 16;
+
+// How the pixels of a frame are shared out between threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    // One row at a time, split across threads by column, waiting for the slowest pixel of each
+    // row before starting the next. Kept for comparison.
+    Rows,
+    // Square tiles of `size` pixels a side over the whole frame, handed to whichever thread is
+    // free next in the given order
+    Tiles { size: usize, order: TileOrder },
+}
+
+impl Default for Schedule {
+    fn default() -> Schedule {
+        Schedule::Tiles {
+            size: DEFAULT_TILE_SIZE,
+            order: TileOrder::default(),
+        }
+    }
+}
+
+impl Schedule {
+    // The pieces of a width x height frame that threads take one at a time. Rows become tiles
+    // one pixel high.
+    pub fn tiles(&self, width: usize, height: usize) -> Vec<Tile> {
+        match *self {
+            Schedule::Rows => (0..height)
+                .map(|y| Tile {
+                    x: 0,
+                    y,
+                    width,
+                    height: 1,
+                })
+                .collect(),
+            Schedule::Tiles { size, order } => tiles(width, height, size, order),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    // Left to right, top to bottom
+    Scanline,
+    // Outwards from the middle of the frame, where the subject usually is
+    #[default]
+    Spiral,
+    // Along a Hilbert curve, so consecutive tiles are neighbours and share cached geometry
+    Hilbert,
+}
+
+// A rectangle of pixels. Tiles on the right and bottom edges may be cut short by the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    // The (x, y) coordinates of every pixel in the tile, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+}
+
+// Covers a width x height frame with tiles of `size` pixels a side, listed in `order`.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+
+    let mut grid: Vec<(usize, usize)> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => grid = spiral(columns, rows),
+        TileOrder::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            grid.sort_by_key(|&(column, row)| hilbert_index(side, column, row));
+        }
+    }
+
+    grid.into_iter()
+        .map(|(column, row)| {
+            let (x, y) = (column * size, row * size);
+            Tile {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+// Walks a square spiral out from the middle cell of a columns x rows grid, keeping the cells
+// that fall inside it.
+fn spiral(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let total = columns * rows;
+    let mut cells = Vec::with_capacity(total);
+    let (mut x, mut y) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    // Right, down, left, up, with legs of 1, 1, 2, 2, 3, 3, ...
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 1;
+    let mut turn = 0;
+
+    while cells.len() < total {
+        for _ in 0..2 {
+            let (dx, dy) = directions[turn % 4];
+            for _ in 0..leg {
+                if (0..columns as i64).contains(&x) && (0..rows as i64).contains(&y) {
+                    cells.push((x as usize, y as usize));
+                }
+                x += dx;
+                y += dy;
+            }
+            turn += 1;
+        }
+        leg += 1;
+    }
+    cells
+}
+
+// The distance along the Hilbert curve filling a side x side grid to the cell (x, y). The side
+// must be a power of two.
+fn hilbert_index(side: usize, x: usize, y: usize) -> usize {
+    let (mut x, mut y) = (x, y);
+    let mut d = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant so the curve inside it joins up with its neighbours
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every pixel of the frame, exactly once
+    fn assert_covers(tiles: &[Tile], width: usize, height: usize) {
+        let mut seen = vec![0; width * height];
+        for tile in tiles {
+            for (x, y) in tile.pixels() {
+                seen[y * width + x] += 1;
+            }
+        }
+        assert!(seen.iter().all(|&n| n == 1), "{seen:?}");
+    }
+
+    #[test]
+    fn tiles_cover_frame_test() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            for (width, height, size) in [(64, 48, 16), (37, 5, 8), (1, 1, 16), (20, 33, 7)] {
+                let tiles = tiles(width, height, size, order);
+                assert_eq!(tiles.len(), width.div_ceil(size) * height.div_ceil(size));
+                assert_covers(&tiles, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn tile_order_test() {
+        let scanline = tiles(48, 32, 16, TileOrder::Scanline);
+        assert_eq!((scanline[1].x, scanline[1].y), (16, 0));
+        assert_eq!((scanline[3].x, scanline[3].y), (0, 16));
+
+        // The spiral starts in the middle and stays next to what it has already visited.
+        let spiral = tiles(48, 48, 16, TileOrder::Spiral);
+        assert_eq!((spiral[0].x, spiral[0].y), (16, 16));
+        assert_eq!((spiral[1].x, spiral[1].y), (32, 16));
+        assert_eq!((spiral[2].x, spiral[2].y), (32, 32));
+
+        // Consecutive steps along the Hilbert curve are always neighbours.
+        let hilbert = tiles(64, 64, 8, TileOrder::Hilbert);
+        for pair in hilbert.windows(2) {
+            let step = pair[0].x.abs_diff(pair[1].x) + pair[0].y.abs_diff(pair[1].y);
+            assert_eq!(step, 8, "{pair:?}");
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// Recording the path of every sample through a pixel, to find out why it looks the way it does.
+
+use glam::DVec3;
+use serde::Serialize;
+
+// Hears about each step of a path as it's traced. Rendering passes `()`, which ignores
+// everything, so the calls compile away.
+pub trait PathRecorder {
+    fn record(&mut self, event: PathEvent);
+}
+
+impl PathRecorder for () {
+    #[inline(always)]
+    fn record(&mut self, _event: PathEvent) {}
+}
+
+impl PathRecorder for Vec<PathEvent> {
+    fn record(&mut self, event: PathEvent) {
+        self.push(event);
+    }
+}
+
+// One step of a path. Bounce 0 is the ray leaving the camera.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PathEvent {
+    CameraRay {
+        origin: DVec3,
+        direction: DVec3,
+        time: f64,
+    },
+    Hit {
+        bounce: i32,
+        t: f64,
+        point: DVec3,
+        normal: DVec3,
+        front_face: bool,
+        material: &'static str,
+        // Emitted light, already scaled by its MIS weight
+        emitted: DVec3,
+    },
+    // The ray left the scene
+    Miss {
+        bounce: i32,
+        background: DVec3,
+    },
+    // The material sent the path on in a new direction
+    Scatter {
+        bounce: i32,
+        direction: DVec3,
+        attenuation: DVec3,
+    },
+    // The material ended the path, or sent it into the surface
+    Absorbed {
+        bounce: i32,
+    },
+    // A shadow ray aimed at a light, and the light it brought back
+    LightSample {
+        bounce: i32,
+        direction: DVec3,
+        pdf: f64,
+        contribution: DVec3,
+    },
+    // The path was cut off at the camera's max_depth
+    DepthLimit {
+        bounce: i32,
+    },
+}
+
+// Every sample a render would take through one pixel, step by step.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PixelTrace {
+    pub x: i32,
+    pub y: i32,
+    pub seed: u64,
+    pub samples: Vec<SampleTrace>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SampleTrace {
+    pub sample: i32,
+    // The radiance the sample brought back
+    pub color: DVec3,
+    pub events: Vec<PathEvent>,
+}
+
+impl PixelTrace {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("traces only hold plain numbers and strings")
+    }
+}
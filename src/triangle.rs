@@ -0,0 +1,354 @@
+use crate::aabb::AABB;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utils;
+use glam::DVec3;
+use rand::Rng;
+
+#[derive(Clone)]
+pub struct Triangle {
+    vertices: [DVec3; 3],
+    normals: Option<[DVec3; 3]>,
+    mat: Material,
+    bbox: AABB,
+}
+
+impl Triangle {
+    // A flat shaded triangle. Counter-clockwise winding, seen from the front, faces outward.
+    pub fn new(v0: DVec3, v1: DVec3, v2: DVec3, mat: Material) -> Triangle {
+        Triangle {
+            vertices: [v0, v1, v2],
+            normals: None,
+            mat,
+            bbox: AABB::from_boxes(&AABB::from_points(v0, v1), &AABB::from_points(v2, v2))
+                .pad_to_minimums(),
+        }
+    }
+
+    // A smooth shaded triangle, interpolating the given per-vertex normals across its face.
+    pub fn with_normals(
+        v0: DVec3,
+        v1: DVec3,
+        v2: DVec3,
+        normals: [DVec3; 3],
+        mat: Material,
+    ) -> Triangle {
+        Triangle {
+            normals: Some(normals.map(|n| n.normalize())),
+            ..Triangle::new(v0, v1, v2, mat)
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let [v0, v1, v2] = self.vertices;
+        let (t, bary) = intersect(r, v0, v1, v2, ray_t)?;
+
+        Some(surface_record(
+            r,
+            t,
+            bary,
+            self.vertices,
+            self.normals,
+            &self.mat,
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bbox)
+    }
+
+    // Converts the uniform density over the triangle's area into one over solid angle.
+    fn pdf_value(&self, origin: DVec3, direction: DVec3) -> f64 {
+        let ray = Ray::with_direction(origin, direction);
+        let Some(rec) = self.hit(&ray, Interval::new(0.001, f64::INFINITY)) else {
+            return 0.0;
+        };
+
+        let [v0, v1, v2] = self.vertices;
+        let area = 0.5 * (v1 - v0).cross(v2 - v0).length();
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = direction.normalize().dot(rec.geometric_normal).abs();
+        if area == 0.0 || cosine == 0.0 {
+            return 0.0;
+        }
+        distance_squared / (cosine * area)
+    }
+
+    // Aims at a point picked uniformly over the triangle's area.
+    fn random(&self, origin: DVec3) -> DVec3 {
+        let mut rng = utils::rng();
+        let sqrt_r1 = rng.gen::<f64>().sqrt();
+        let r2 = rng.gen::<f64>();
+
+        let [v0, v1, v2] = self.vertices;
+        let p = (1.0 - sqrt_r1) * v0 + sqrt_r1 * (1.0 - r2) * v1 + sqrt_r1 * r2 * v2;
+        p - origin
+    }
+}
+
+// Builds the hit record for a triangle hit at `t` with barycentric coordinates `bary`, where
+// `bary[i]` is the weight of vertex `i`.
+pub fn surface_record<'a>(
+    r: &Ray,
+    t: f64,
+    bary: DVec3,
+    vertices: [DVec3; 3],
+    normals: Option<[DVec3; 3]>,
+    mat: &'a Material,
+) -> HitRecord<'a> {
+    let [v0, v1, v2] = vertices;
+
+    let mut rec = HitRecord {
+        p: bary.x * v0 + bary.y * v1 + bary.z * v2,
+        t,
+        u: bary.y,
+        v: bary.z,
+        mat,
+        ..Default::default()
+    };
+
+    let outward_normal = (v1 - v0).cross(v2 - v0).normalize();
+    rec.set_face_normal(r, outward_normal);
+
+    if let Some([n0, n1, n2]) = normals {
+        let shading_normal = (bary.x * n0 + bary.y * n1 + bary.z * n2).normalize();
+        // Keep the shading normal on the same side of the surface as the geometric one, so
+        // materials agree with `front_face` about which side was hit.
+        rec.normal = if shading_normal.dot(rec.geometric_normal) < 0.0 {
+            -shading_normal
+        } else {
+            shading_normal
+        };
+    }
+
+    rec
+}
+
+// Watertight ray/triangle intersection (Woop, Benthin and Wald, JCGT 2013).
+//
+// The triangle is transformed into a space where the ray starts at the origin and points down
+// +z, which reduces the inside test to the signs of three 2D edge functions. Because a shared edge
+// produces exactly the same edge function (negated) for both of its triangles, a ray can never
+// slip through the crack between them.
+//
+// Returns the ray parameter of the hit along with the barycentric weights of v0, v1 and v2.
+pub fn intersect(
+    r: &Ray,
+    v0: DVec3,
+    v1: DVec3,
+    v2: DVec3,
+    ray_t: Interval,
+) -> Option<(f64, DVec3)> {
+    // Make the dimension where the ray direction is largest the new z axis.
+    let d = r.direction.abs();
+    let kz = if d.x > d.y && d.x > d.z {
+        0
+    } else if d.y > d.z {
+        1
+    } else {
+        2
+    };
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+
+    // Swap the other two axes if needed to preserve the winding of the triangle.
+    if r.direction[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // Shear constants that map the ray direction onto +z.
+    let dz = r.direction[kz];
+    let sx = r.direction[kx] / dz;
+    let sy = r.direction[ky] / dz;
+    let sz = 1.0 / dz;
+
+    // Vertices relative to the ray origin.
+    let a = v0 - r.origin;
+    let b = v1 - r.origin;
+    let c = v2 - r.origin;
+
+    // Shear and scale the vertices.
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    // Scaled barycentric coordinates, from the edge functions opposite each vertex.
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    // The ray passes outside an edge. Mixed signs mean outside, but zeros are allowed so rays
+    // through an edge or vertex still hit.
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    // The ray is parallel to the plane of the triangle.
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    // Scaled hit distance, divided through by the determinant only once we know it's needed.
+    let az = sz * a[kz];
+    let bz = sz * b[kz];
+    let cz = sz * c[kz];
+    let t = (u * az + v * bz + w * cz) / det;
+
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some((t, DVec3::new(u, v, w) / det))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random_dvec3_unit;
+
+    fn white() -> Material {
+        Material::lambertian(DVec3::ONE)
+    }
+
+    fn forward() -> Interval {
+        Interval::new(f64::EPSILON, f64::INFINITY)
+    }
+
+    #[test]
+    fn hit_center_test() {
+        let tri = Triangle::new(
+            DVec3::new(-1.0, -1.0, -2.0),
+            DVec3::new(1.0, -1.0, -2.0),
+            DVec3::new(0.0, 1.0, -2.0),
+            white(),
+        );
+        let r = Ray::with_direction(DVec3::ZERO, DVec3::new(0.0, 0.0, -1.0));
+
+        let rec = tri.hit(&r, forward()).unwrap();
+
+        assert!((rec.t - 2.0).abs() < f64::EPSILON);
+        assert!(rec.front_face);
+        assert!((rec.normal - DVec3::Z).length() < f64::EPSILON);
+        assert!((rec.geometric_normal - DVec3::Z).length() < f64::EPSILON);
+
+        // Barycentric coordinates reconstruct the hit point.
+        let w = 1.0 - rec.u - rec.v;
+        let p = w * DVec3::new(-1.0, -1.0, -2.0)
+            + rec.u * DVec3::new(1.0, -1.0, -2.0)
+            + rec.v * DVec3::new(0.0, 1.0, -2.0);
+        assert!((p - rec.p).length() < 1e-12);
+        assert!((rec.v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn miss_outside_test() {
+        let tri = Triangle::new(DVec3::ZERO, DVec3::X, DVec3::Y, white());
+        let r = Ray::with_direction(DVec3::new(1.0, 1.0, 1.0), -DVec3::Z);
+
+        assert!(tri.hit(&r, forward()).is_none());
+
+        // Behind the origin of the ray
+        let r = Ray::with_direction(DVec3::new(0.2, 0.2, -1.0), -DVec3::Z);
+        assert!(tri.hit(&r, forward()).is_none());
+    }
+
+    #[test]
+    fn back_face_test() {
+        let tri = Triangle::new(DVec3::ZERO, DVec3::X, DVec3::Y, white());
+        let r = Ray::with_direction(DVec3::new(0.2, 0.2, -1.0), DVec3::Z);
+
+        let rec = tri.hit(&r, forward()).unwrap();
+
+        assert!(!rec.front_face);
+        assert!((rec.normal + DVec3::Z).length() < f64::EPSILON);
+    }
+
+    #[test]
+    fn shared_edge_is_watertight_test() {
+        // Two triangles forming a unit square, split along the diagonal.
+        let a = DVec3::new(0.0, 0.0, 0.0);
+        let b = DVec3::new(1.0, 0.0, 0.0);
+        let c = DVec3::new(1.0, 1.0, 0.0);
+        let d = DVec3::new(0.0, 1.0, 0.0);
+        let lower = Triangle::new(a, b, c, white());
+        let upper = Triangle::new(a, c, d, white());
+
+        // Aim a fan of rays from awkward origins straight at points on the shared diagonal.
+        for i in 1..1000 {
+            let s = i as f64 / 1000.0;
+            let target = DVec3::new(s, s, 0.0);
+            let origin = DVec3::new(0.3 + s * 0.17, -0.7 + s * 0.01, 3.1);
+            let r = Ray::with_direction(origin, target - origin);
+
+            let hits = lower.hit(&r, forward()).is_some() as i32
+                + upper.hit(&r, forward()).is_some() as i32;
+            assert!(hits >= 1, "ray through {target:?} leaked between triangles");
+        }
+    }
+
+    #[test]
+    fn interpolated_normal_test() {
+        let tri = Triangle::with_normals(
+            DVec3::ZERO,
+            DVec3::X,
+            DVec3::Y,
+            [DVec3::Z, DVec3::new(1.0, 0.0, 1.0), DVec3::Z],
+            white(),
+        );
+        let r = Ray::with_direction(DVec3::new(0.5, 0.0, 1.0), -DVec3::Z);
+        let rec = tri.hit(&r, forward()).unwrap();
+
+        let expected = (0.5 * DVec3::Z + 0.5 * DVec3::new(1.0, 0.0, 1.0).normalize()).normalize();
+        assert!((rec.normal - expected).length() < 1e-12);
+        assert!((rec.geometric_normal - DVec3::Z).length() < f64::EPSILON);
+
+        // The shading normal flips with the geometric normal when hit from behind.
+        let r = Ray::with_direction(DVec3::new(0.5, 0.0, -1.0), DVec3::Z);
+        let rec = tri.hit(&r, forward()).unwrap();
+        assert!((rec.normal + expected).length() < 1e-12);
+    }
+
+    #[test]
+    fn axis_aligned_bounding_box_is_padded_test() {
+        let tri = Triangle::new(DVec3::ZERO, DVec3::X, DVec3::Y, white());
+        let bbox = tri.bounding_box().unwrap();
+
+        assert!(bbox.z.size() > 0.0);
+
+        let r = Ray::with_direction(DVec3::new(0.25, 0.25, 1.0), -DVec3::Z);
+        assert!(bbox.hit(r, forward()));
+    }
+
+    #[test]
+    fn light_pdf_test() {
+        let tri = Triangle::new(
+            DVec3::new(-1.0, 1.0, -1.0),
+            DVec3::new(1.0, 1.0, -1.0),
+            DVec3::new(0.0, 1.0, 1.0),
+            white(),
+        );
+        let origin = DVec3::ZERO;
+
+        // Sampled directions all point at the triangle.
+        for _ in 0..100 {
+            assert!(tri.pdf_value(origin, tri.random(origin)) > 0.0);
+        }
+
+        // The density integrates to one over the sphere of directions.
+        let samples = 200_000;
+        let total: f64 = (0..samples)
+            .map(|_| tri.pdf_value(origin, random_dvec3_unit()))
+            .sum();
+        let integral = total / samples as f64 * 4.0 * std::f64::consts::PI;
+        assert!((integral - 1.0).abs() < 0.03, "{integral}");
+    }
+}
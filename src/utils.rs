@@ -1,11 +1,59 @@
 use glam::DVec3;
-use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    // The generator behind every random draw while rendering. It starts from entropy, and the
+    // camera reseeds it before each sample so a render doesn't depend on how rayon schedules it.
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+// A handle to this thread's generator, used like `rng()`.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalRng;
+
+pub fn rng() -> LocalRng {
+    LocalRng
+}
+
+impl RngCore for LocalRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+// Restarts this thread's generator on the stream for one (pixel, sample) pair of a render.
+// Nearby streams are decorrelated by mixing all the parts through SplitMix64.
+pub fn reseed(seed: u64, pixel: u64, sample: u64) {
+    let stream = splitmix64(splitmix64(splitmix64(seed) ^ pixel) ^ sample);
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(stream));
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
 
 // pub struct Utils {}
 
 // impl Utils{
 pub fn _random_dvec3() -> DVec3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     DVec3::new(
         rng.gen::<f64>() - 0.5,
         rng.gen::<f64>() - 0.5,
@@ -15,15 +63,15 @@ pub fn _random_dvec3() -> DVec3 {
 }
 
 pub fn random_dvec3_range(min: f64, max: f64) -> DVec3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     DVec3::new(
         rng.gen_range(min..=max),
         rng.gen_range(min..=max),
         rng.gen_range(min..=max),
     )
-    .normalize()
 }
 
+// A uniformly distributed direction, by rejecting points of the cube outside the unit ball.
 pub fn random_dvec3_unit() -> DVec3 {
     loop {
         let p = random_dvec3_range(-1.0, 1.0);
@@ -35,10 +83,31 @@ pub fn random_dvec3_unit() -> DVec3 {
 }
 
 pub fn random_in_unit_disc() -> DVec3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     DVec3::new(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5, 0.0).normalize() * rng.gen::<f64>()
 }
 
 pub fn near_zero(test: &DVec3) -> bool {
     test.length() < 0.00001
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reseed_test() {
+        let draw = || (rng().gen::<u64>(), random_dvec3_unit());
+
+        reseed(1, 2, 3);
+        let first = draw();
+        reseed(1, 2, 3);
+        assert_eq!(first, draw());
+
+        // Neighbouring pixels, samples and seeds all get different streams.
+        for (seed, pixel, sample) in [(1, 2, 4), (1, 3, 3), (2, 2, 3), (1, 3, 2)] {
+            reseed(seed, pixel, sample);
+            assert_ne!(first, draw());
+        }
+    }
+}
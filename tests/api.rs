@@ -0,0 +1,132 @@
+// Drives the renderer the way an embedding frontend would, through the public API only.
+
+use glam::DVec3;
+use rayrusting::output::OutputFormat;
+use rayrusting::{BvhNode, Camera, Error, Hittable, HittableList, Material, Sphere, SplitStrategy};
+
+// A black sphere filling the middle of the view, with sky around it.
+fn world() -> HittableList {
+    let mut world = HittableList::new();
+    world
+        .add(Box::new(Sphere::new_stationary(
+            DVec3::new(0.0, 0.0, -1.0),
+            0.5,
+            Material::lambertian(DVec3::ZERO),
+        )))
+        .unwrap();
+    world
+}
+
+fn camera() -> Camera {
+    Camera::builder()
+        .image_width(5)
+        .samples_per_pixel(1)
+        .max_depth(4)
+        .look_from(DVec3::ZERO)
+        .look_at(DVec3::new(0.0, 0.0, -1.0))
+        .build()
+}
+
+#[test]
+fn render_to_buffer_test() {
+    let image = camera().render_to_buffer(&world(), &HittableList::new());
+
+    assert_eq!((image.width, image.height), (5, 5));
+    // The sphere absorbs everything, so the middle is black.
+    assert_eq!(image.get(2, 2), DVec3::ZERO);
+    // The corners see the sky.
+    for (x, y) in [(0, 0), (4, 0), (0, 4), (4, 4)] {
+        assert!(image.get(x, y).min_element() > 0.4, "pixel ({x}, {y})");
+    }
+}
+
+#[test]
+fn bvh_matches_list_test() {
+    let list = camera().render_to_buffer(&world(), &HittableList::new());
+    let bvh = BvhNode::from_list(world(), SplitStrategy::Sah);
+    assert!(bvh.bounding_box().is_some());
+
+    let image = camera().render_to_buffer(&bvh, &HittableList::new());
+    assert_eq!(image.get(2, 2), list.get(2, 2));
+}
+
+#[test]
+fn encode_test() {
+    let image = camera().render_to_buffer(&world(), &HittableList::new());
+
+    let mut ppm = Vec::new();
+    image.encode(&mut ppm, OutputFormat::PpmAscii).unwrap();
+    assert!(ppm.starts_with(b"P3\n5 5\n255\n"));
+}
+
+#[test]
+fn render_errors_test() {
+    let mut camera = camera();
+    camera.output = "image.bmp".into();
+    let result = camera.render(&world(), &HittableList::new());
+    assert!(matches!(result, Err(Error::UnknownFormat(_))));
+
+    let dir = std::env::temp_dir().join("rayrusting-no-such-dir");
+    camera.output = dir.join("image.ppm");
+    match camera.render(&world(), &HittableList::new()) {
+        Err(Error::Write { path, .. }) => assert_eq!(path, camera.output),
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn example_scene_test() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/three_spheres.toml");
+    let scene = rayrusting::scene::load_scene(path).unwrap();
+    assert!(!scene.world.is_empty());
+    assert!(scene.lights.is_empty());
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/sphere_light.toml");
+    let scene = rayrusting::scene::load_scene(path).unwrap();
+    assert_eq!(scene.lights.len(), 1);
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/cornell_box.toml");
+    let scene = rayrusting::scene::load_scene(path).unwrap();
+    assert_eq!(scene.world.len(), 8);
+    assert_eq!(scene.lights.len(), 1);
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/cornell_smoke.toml");
+    let scene = rayrusting::scene::load_scene(path).unwrap();
+    assert_eq!(scene.world.len(), 8);
+    assert_eq!(scene.lights.len(), 1);
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/cloud.toml");
+    let scene = rayrusting::scene::load_scene(path).unwrap();
+    assert_eq!(scene.world.len(), 2);
+    assert_eq!(scene.lights.len(), 0);
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/principled.toml");
+    let scene = rayrusting::scene::load_scene(path).unwrap();
+    assert_eq!(scene.world.len(), 7);
+    assert_eq!(scene.lights.len(), 1);
+}
+
+#[test]
+fn seeded_render_test() {
+    // The built-in scene has glass, metal, defocus blur and motion blur, so it exercises every
+    // random draw the renderer makes.
+    let render = |seed: u64, threads: usize| {
+        let scene = rayrusting::scene::default_scene(Some(seed));
+        let mut camera = scene.camera;
+        camera.image_width = 16;
+        camera.samples_per_pixel = 4;
+        camera.max_depth = 8;
+        let world = BvhNode::from_list(scene.world, SplitStrategy::Sah);
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| camera.render_to_buffer(&world, &scene.lights))
+    };
+
+    let image = render(3, 1);
+    assert_eq!(image.pixels, render(3, 1).pixels);
+    assert_eq!(image.pixels, render(3, 3).pixels);
+    assert_ne!(image.pixels, render(4, 1).pixels);
+}
@@ -1,5 +1,7 @@
 use xaction::cmd;
 
+// `cmd!` expands to a cfg that rustc doesn't know about.
+#[allow(unexpected_cfgs)]
 #[test]
 fn test_formatting() {
     cmd!("cargo fmt --all -- --check").run().unwrap()